@@ -2,6 +2,7 @@ import {loadAsync} from 'jszip'
 import xashURL from 'xash3d-fwgs/xash.wasm?url'
 import gl4esURL from 'xash3d-fwgs/libref_webgl2.wasm?url'
 import {Xash3DWebRTC} from "./webrtc";
+import {ChannelMode} from "./protocol";
 
 const touchControls = document.getElementById('touchControls') as HTMLInputElement
 touchControls.addEventListener('change', () => {
@@ -13,6 +14,18 @@ const usernamePromise = new Promise<string>(resolve => {
     usernamePromiseResolve = resolve
 })
 
+// Invite links carry a token as `?auth=`, otherwise ask for the password
+function authCredential(required: boolean): Promise<string | undefined> | undefined {
+    if (!required) return undefined
+    const token = new URLSearchParams(window.location.search).get('auth')
+    if (token) return Promise.resolve(token)
+
+    const password = document.getElementById('password') as HTMLInputElement
+    password.required = true
+    document.getElementById('passwordField')!.hidden = false
+    return usernamePromise.then(() => password.value)
+}
+
 async function fetchWithProgress(url: string) {
     const progress = document.getElementById('progress') as HTMLProgressElement
     const res = await fetch(url);
@@ -44,9 +57,63 @@ async function fetchWithProgress(url: string) {
     return blob.arrayBuffer()
 }
 
+// Server selected via `/play/<name>`, otherwise the proxy's default server
+function selectedServer(): string | undefined {
+    const match = window.location.pathname.match(/^\/play\/([^/]+)/)
+    return match ? decodeURIComponent(match[1]) : undefined
+}
+
+interface ServerDetails {
+    info: {name: string, map: string, players: number, max_players: number, bots: number}
+    players: {name: string, score: number, duration_secs: number}[] | null
+}
+
+// Show server name, map and players on the join form until the game starts
+async function showLobby(server?: string) {
+    const form = document.getElementById('form') as HTMLFormElement
+    const url = server ? `/api/server/info?server=${encodeURIComponent(server)}` : '/api/server/info'
+
+    while (form.style.display !== 'none') {
+        try {
+            const res = await fetch(url)
+            if (res.ok) {
+                renderLobby(await res.json() as ServerDetails)
+            }
+        } catch (e) {
+            console.warn('Server info unavailable', e)
+        }
+        await new Promise(resolve => setTimeout(resolve, 10000))
+    }
+}
+
+function renderLobby({info, players}: ServerDetails) {
+    document.getElementById('lobbyName')!.textContent = info.name
+    document.getElementById('lobbyStatus')!.textContent =
+        `${info.map} · ${info.players}/${info.max_players} players` + (info.bots ? ` (${info.bots} bots)` : '')
+
+    const list = document.getElementById('lobbyPlayers')!
+    list.replaceChildren(...(players ?? [])
+        .filter(p => p.name)
+        .sort((a, b) => b.score - a.score)
+        .map(p => {
+            const item = document.createElement('li')
+            const name = document.createElement('span')
+            name.textContent = p.name
+            const stats = document.createElement('span')
+            stats.textContent = `${p.score} · ${Math.floor(p.duration_secs / 60)} min`
+            item.append(name, stats)
+            return item
+        }))
+
+    document.getElementById('lobby')!.hidden = false
+}
+
 async function main() {
     // Load dynamic configuration from server (environment variables)
-    const config = await fetch("/config").then(res => res.json()) as Awaited<{
+    const server = selectedServer()
+    const configUrl = server ? `/config?server=${encodeURIComponent(server)}` : '/config'
+    const config = await fetch(configUrl).then(res => res.json()) as Awaited<{
+        server: string;
         arguments: string[];
         console: string[];
         game_dir: string;
@@ -61,6 +128,8 @@ async function main() {
         files_map: Record<string, string>;
         proxy_host: string;
         proxy_port: number;
+        webtransport_port: number | null;
+        auth_required: boolean;
     }>
 
     // Use URLs directly from server config (no imports needed)
@@ -81,11 +150,17 @@ async function main() {
         filesMap: config.files_map,
         proxyHost: config.proxy_host,
         proxyPort: config.proxy_port,
+        server: config.server,
+        target: new URLSearchParams(window.location.search).get('target') ?? undefined,
+        forceWebSocket: new URLSearchParams(window.location.search).get('transport') === 'websocket',
+        webTransportPort: config.webtransport_port ?? undefined,
+        channelMode: (new URLSearchParams(window.location.search).get('channel_mode') ?? undefined) as ChannelMode | undefined,
+        auth: authCredential(config.auth_required),
     });
 
     const [zip, extras] = await Promise.all([
         (async () => {
-            const res = await fetchWithProgress('valve.zip')
+            const res = await fetchWithProgress('/valve.zip')
             return await loadAsync(res);
         })(),
         (async () => {
@@ -158,4 +233,5 @@ if (username) {
     usernamePromiseResolve(username)
 })
 
+showLobby(selectedServer())
 main()
\ No newline at end of file
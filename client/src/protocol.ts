@@ -0,0 +1,67 @@
+// Signaling protocol types, mirroring `src/protocol.rs` in the proxy.
+// Keep both files in sync and bump PROTOCOL_VERSION on incompatible changes.
+
+export const PROTOCOL_VERSION = 1
+
+export type ErrorCode =
+    | 'invalid_message'
+    | 'handshake_required'
+    | 'unsupported_version'
+    | 'peer_connection_failed'
+    | 'data_channel_failed'
+    | 'offer_failed'
+    | 'invalid_answer'
+    | 'invalid_candidate'
+    | 'ice_failed'
+    | 'target_refused'
+    | 'bridge_failed'
+    | 'disconnected'
+    | 'too_many_sessions'
+    | 'rate_limited'
+    | 'unauthorized'
+
+export type ChannelMode = 'reliable' | 'unordered' | 'unreliable' | 'lifetime'
+
+export type TransportKind = 'webrtc' | 'websocket' | 'webtransport'
+
+export interface SessionDescription {
+    type: 'offer' | 'answer'
+    sdp: string
+}
+
+export interface ServerHello {
+    version: number
+    capabilities: string[]
+    server: string
+    ice_servers: RTCIceServer[]
+    channel_mode: ChannelMode
+}
+
+export interface DirectionStats {
+    packets: number
+    bytes: number
+    jitter_ms: number
+    dropped: number
+}
+
+export interface SessionStats {
+    to_server: DirectionStats
+    to_client: DirectionStats
+    buffered_amount: number | null
+}
+
+export type ClientMessage =
+    | { event: 'hello', data: { version: number, capabilities: string[], channel_mode?: ChannelMode, auth?: string } }
+    | { event: 'answer', data: SessionDescription }
+    | { event: 'candidate', data: RTCIceCandidateInit }
+    | { event: 'use_websocket' }
+
+export type ServerMessage =
+    | { event: 'hello', data: ServerHello }
+    | { event: 'offer', data: SessionDescription }
+    | { event: 'candidate', data: RTCIceCandidateInit }
+    | { event: 'bridge_ready', data: { server: string, transport: TransportKind } }
+    | { event: 'server_unreachable', data: { message: string } }
+    | { event: 'draining', data: { deadline_secs: number } }
+    | { event: 'stats', data: SessionStats }
+    | { event: 'error', data: { code: ErrorCode, message: string } }
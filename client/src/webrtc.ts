@@ -1,8 +1,19 @@
 import {Net, Packet, Xash3D, Xash3DOptions} from "xash3d-fwgs";
+import {ChannelMode, ClientMessage, PROTOCOL_VERSION, ServerMessage, SessionStats} from "./protocol";
 
 export interface Xash3DWebRTCOptions extends Xash3DOptions {
     proxyHost: string;
     proxyPort: number;
+    server: string;
+    target?: string;
+    // Skip WebRTC and carry game packets over the signaling WebSocket
+    forceWebSocket?: boolean;
+    // Proxy's WebTransport port, tried before WebRTC when the browser supports it
+    webTransportPort?: number;
+    // Data channel delivery mode, defaults to the proxy's choice
+    channelMode?: ChannelMode;
+    // Password or token, awaited before connecting when the proxy requires one
+    auth?: Promise<string | undefined>;
 }
 
 export class Xash3DWebRTC extends Xash3D {
@@ -10,7 +21,7 @@ export class Xash3DWebRTC extends Xash3D {
     private resolve?: (value?: unknown) => void
     private ws?: WebSocket
     private peer?: RTCPeerConnection
-    private remoteDescription?: RTCSessionDescription
+    private remoteDescription?: RTCSessionDescriptionInit
     private candidates: RTCIceCandidateInit[] = []
     private wasRemote = false
     private timeout?: ReturnType<typeof setTimeout>
@@ -18,6 +29,17 @@ export class Xash3DWebRTC extends Xash3D {
     private proxyHost: string
     private proxyPort: number
     private proxyIp: [number, number, number, number]
+    private server: string
+    private target?: string
+    private forceWebSocket: boolean
+    private capabilities: string[] = []
+    private wsTransport = false
+    private webTransportPort?: number
+    private channelMode?: ChannelMode
+    private authPromise?: Promise<string | undefined>
+    private auth?: string
+    private datagrams?: WritableStreamDefaultWriter<Uint8Array>
+    private lastStats?: { stats: SessionStats, at: number }
 
     constructor(opts: Xash3DWebRTCOptions) {
         super(opts);
@@ -25,6 +47,12 @@ export class Xash3DWebRTC extends Xash3D {
         this.proxyHost = opts.proxyHost
         this.proxyPort = opts.proxyPort
         this.proxyIp = this.parseIp(opts.proxyHost)
+        this.server = opts.server
+        this.target = opts.target
+        this.forceWebSocket = opts.forceWebSocket ?? false
+        this.webTransportPort = opts.webTransportPort
+        this.channelMode = opts.channelMode
+        this.authPromise = opts.auth
     }
 
     private parseIp(host: string): [number, number, number, number] {
@@ -43,13 +71,13 @@ export class Xash3DWebRTC extends Xash3D {
         ]);
     }
 
-    startConnection() {
-        this.peer = new RTCPeerConnection()
+    startConnection(iceServers: RTCIceServer[]) {
+        this.peer = new RTCPeerConnection({iceServers})
         this.peer.onicecandidate = e => {
             if (!e.candidate) {
                 return
             }
-            this.wsSend('candidate', e.candidate.toJSON())
+            this.wsSend({event: 'candidate', data: e.candidate.toJSON()})
         }
         let el: HTMLAudioElement | undefined
         this.peer.ontrack = (e) => {
@@ -76,7 +104,11 @@ export class Xash3DWebRTC extends Xash3D {
                 el = undefined
             }
             if (this.peer?.connectionState === 'failed') {
-                this.connectWs()
+                if (this.capabilities.includes('websocket_transport')) {
+                    this.useWebSocket()
+                } else {
+                    this.connectWs()
+                }
             }
         }
         this.stream?.getTracks()?.forEach(t => {
@@ -107,22 +139,36 @@ export class Xash3DWebRTC extends Xash3D {
                     this.channel = e.channel
                 }
                 if (channelsCount === 2) {
-                    if (this.resolve) {
-                        const r = this.resolve
-                        this.resolve = undefined
-                        if (this.timeout) {
-                            clearTimeout(this.timeout)
-                            this.timeout = undefined
-                        }
-                        document.getElementById('warning')!.style.opacity = '0'
-                        r()
-                    }
+                    this.connected()
                 }
             }
         }
         this.handleDescription()
     }
 
+    // Resolve the pending connect() once a transport is ready
+    private connected() {
+        if (!this.resolve) return
+
+        const r = this.resolve
+        this.resolve = undefined
+        if (this.timeout) {
+            clearTimeout(this.timeout)
+            this.timeout = undefined
+        }
+        document.getElementById('warning')!.style.opacity = '0'
+        r()
+    }
+
+    // Fall back to binary WebSocket frames when WebRTC cannot connect
+    private useWebSocket() {
+        console.warn('WebRTC unavailable, using WebSocket transport')
+        this.peer?.close()
+        this.peer = undefined
+        this.channel = undefined
+        this.wsSend({event: 'use_websocket'})
+    }
+
     private async getUserMedia() {
         try {
             return await navigator.mediaDevices.getUserMedia({audio: true})
@@ -131,12 +177,43 @@ export class Xash3DWebRTC extends Xash3D {
         }
     }
 
-    private wsSend(event: string, data: unknown) {
-        const msg = JSON.stringify({
-            event,
-            data
-        })
-        this.ws?.send(msg)
+    private showWarning(text: string | undefined) {
+        const warning = document.getElementById('warning')!
+        if (text) {
+            warning.textContent = text
+        }
+        warning.style.opacity = text ? '1' : '0'
+    }
+
+    // Show packet rates, jitter and queued bytes so players can tell where lag comes from
+    private showStats(stats: SessionStats) {
+        const now = performance.now()
+        const last = this.lastStats
+        this.lastStats = {stats, at: now}
+        if (!last) return
+
+        const secs = (now - last.at) / 1000
+        const rate = (dir: 'to_server' | 'to_client') => {
+            const packets = (stats[dir].packets - last.stats[dir].packets) / secs
+            const kbytes = (stats[dir].bytes - last.stats[dir].bytes) / secs / 1024
+            return `${packets.toFixed(0)} pkt/s ${kbytes.toFixed(1)} KB/s jitter ${stats[dir].jitter_ms.toFixed(1)} ms`
+        }
+        const parts = [`↑ ${rate('to_server')}`, `↓ ${rate('to_client')}`]
+        const dropped = stats.to_server.dropped + stats.to_client.dropped
+        if (dropped > 0) {
+            parts.push(`dropped ${dropped}`)
+        }
+        if (stats.buffered_amount !== null) {
+            parts.push(`queued ${stats.buffered_amount} B`)
+        }
+
+        const el = document.getElementById('netstats')!
+        el.textContent = parts.join(' | ')
+        el.hidden = false
+    }
+
+    private wsSend(msg: ClientMessage) {
+        this.ws?.send(JSON.stringify(msg))
     }
 
     private async handleDescription() {
@@ -146,7 +223,7 @@ export class Xash3DWebRTC extends Xash3D {
         this.remoteDescription = undefined
         const answer = await this.peer!.createAnswer()
         await this.peer!.setLocalDescription(answer)
-        this.wsSend('answer', answer)
+        this.wsSend({event: 'answer', data: {type: 'answer', sdp: answer.sdp ?? ''}})
         this.wasRemote = true
         this.handleCandidates()
     }
@@ -170,8 +247,25 @@ export class Xash3DWebRTC extends Xash3D {
         const protocol = window.location.protocol === "https:" ? "wss" : "ws";
         const host = window.location.host;
         const handler = async (e: MessageEvent) => {
-            const parsed = JSON.parse(e.data)
+            if (e.data instanceof ArrayBuffer) {
+                (this.net as Net).incoming.enqueue({
+                    ip: this.proxyIp,
+                    port: this.proxyPort,
+                    data: new Int8Array(e.data)
+                })
+                return
+            }
+            const parsed = JSON.parse(e.data) as ServerMessage
             switch (parsed.event) {
+                case 'hello':
+                    console.log(`Proxy protocol v${parsed.data.version}, server ${parsed.data.server}, channels ${parsed.data.channel_mode}`)
+                    this.capabilities = parsed.data.capabilities
+                    if (this.forceWebSocket && this.capabilities.includes('websocket_transport')) {
+                        this.useWebSocket()
+                    } else {
+                        this.startConnection(parsed.data.ice_servers)
+                    }
+                    break
                 case 'offer':
                     this.remoteDescription = parsed.data
                     await this.handleDescription()
@@ -182,15 +276,44 @@ export class Xash3DWebRTC extends Xash3D {
                         this.handleCandidates()
                     }
                     break
+                case 'bridge_ready':
+                    this.wsTransport = parsed.data.transport === 'websocket'
+                    this.showWarning(undefined)
+                    if (this.wsTransport) {
+                        this.connected()
+                    }
+                    break
+                case 'server_unreachable':
+                    this.showWarning(`Game server unreachable: ${parsed.data.message}`)
+                    break
+                case 'draining':
+                    this.showWarning(`Proxy is restarting, you will be disconnected within ${parsed.data.deadline_secs} s`)
+                    break
+                case 'stats':
+                    this.showStats(parsed.data)
+                    break
+                case 'error':
+                    console.error(`Proxy error [${parsed.data.code}]: ${parsed.data.message}`)
+                    this.showWarning(`Connection error: ${parsed.data.message}`)
+                    break
             }
         }
-        this.ws = new WebSocket(`${protocol}://${host}/websocket`);
+        const params = new URLSearchParams({server: this.server})
+        if (this.target) {
+            params.set('target', this.target)
+        }
+        this.wsTransport = false
+        this.ws = new WebSocket(`${protocol}://${host}/websocket?${params}`);
+        this.ws.binaryType = 'arraybuffer'
         this.ws.onerror = () => {
             this.connectWs()
         }
         this.ws.addEventListener('message', handler)
         this.ws.onopen = () => {
-            this.startConnection()
+            this.wsSend({
+                event: 'hello',
+                data: {version: PROTOCOL_VERSION, capabilities: [], channel_mode: this.channelMode, auth: this.auth}
+            })
             if (!this.stream) {
                 this.timeout = setTimeout(() => {
                     this.timeout = undefined
@@ -200,8 +323,52 @@ export class Xash3DWebRTC extends Xash3D {
         }
     }
 
+    // Open a WebTransport session; game packets travel as datagrams
+    private async connectWebTransport(port: number): Promise<boolean> {
+        if (this.forceWebSocket || typeof WebTransport === 'undefined') return false
+
+        const params = new URLSearchParams({server: this.server})
+        if (this.target) {
+            params.set('target', this.target)
+        }
+        if (this.auth) {
+            params.set('auth', this.auth)
+        }
+        try {
+            const transport = new WebTransport(`https://${window.location.hostname}:${port}/webtransport?${params}`)
+            await transport.ready
+            this.datagrams = transport.datagrams.writable.getWriter()
+            this.readDatagrams(transport)
+            transport.closed.catch(() => undefined).finally(() => {
+                this.datagrams = undefined
+                this.showWarning('WebTransport session closed')
+            })
+            return true
+        } catch (e) {
+            console.warn('WebTransport unavailable, falling back to WebRTC', e)
+            return false
+        }
+    }
+
+    private async readDatagrams(transport: WebTransport) {
+        const reader = transport.datagrams.readable.getReader()
+        while (true) {
+            const {value, done} = await reader.read()
+            if (done) break
+            (this.net as Net).incoming.enqueue({
+                ip: this.proxyIp,
+                port: this.proxyPort,
+                data: new Int8Array(value.buffer, value.byteOffset, value.byteLength)
+            })
+        }
+    }
+
     async connect() {
         this.stream = await this.getUserMedia()
+        this.auth = await this.authPromise
+        if (this.webTransportPort && await this.connectWebTransport(this.webTransportPort)) {
+            return
+        }
         return new Promise(resolve => {
             this.resolve = resolve;
             this.connectWs()
@@ -209,6 +376,14 @@ export class Xash3DWebRTC extends Xash3D {
     }
 
     sendto(packet: Packet) {
+        if (this.datagrams) {
+            this.datagrams.write(new Uint8Array(packet.data)).catch(() => undefined)
+            return
+        }
+        if (this.wsTransport) {
+            this.ws?.send(packet.data)
+            return
+        }
         if (!this.channel) return
         this.channel.send(packet.data)
     }
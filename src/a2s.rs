@@ -0,0 +1,547 @@
+//! Valve A2S server queries over UDP.
+//!
+//! Implements `A2S_INFO`, `A2S_PLAYER` and `A2S_RULES` including the challenge
+//! round trip newer HLDS builds require. Info responses are parsed in both the
+//! Source (`I`) and the obsolete `GoldSrc` (`m`) format; split responses are
+//! reassembled in the `GoldSrc` multi-packet format HLDS uses.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+/// Header of every single-packet query and response
+const SINGLE_PACKET: [u8; 4] = [0xFF; 4];
+
+/// Header of one part of a split response
+const MULTI_PACKET: [u8; 4] = [0xFE, 0xFF, 0xFF, 0xFF];
+
+/// Request types and the `A2S_INFO` payload
+const INFO_REQUEST: u8 = b'T';
+const INFO_PAYLOAD: &[u8] = b"Source Engine Query\0";
+const PLAYER_REQUEST: u8 = b'U';
+const RULES_REQUEST: u8 = b'V';
+
+/// Challenge placeholder for player and rules requests
+const NO_CHALLENGE: [u8; 4] = [0xFF; 4];
+
+/// Response types
+const CHALLENGE_RESPONSE: u8 = b'A';
+const INFO_RESPONSE: u8 = b'I';
+const GOLDSRC_INFO_RESPONSE: u8 = b'm';
+const PLAYER_RESPONSE: u8 = b'D';
+const RULES_RESPONSE: u8 = b'E';
+
+/// Upper bound on the parts of a split response
+const MAX_PACKETS: usize = 16;
+
+/// Largest response datagram we accept
+const MAX_PACKET_SIZE: usize = 1400;
+
+/// Reasons a query failed
+#[derive(Debug)]
+pub enum A2sError {
+    /// Socket or address resolution error
+    Io(std::io::Error),
+    /// No answer within the timeout
+    Timeout,
+    /// The answer could not be parsed
+    Malformed(&'static str),
+}
+
+impl fmt::Display for A2sError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "query failed: {e}"),
+            Self::Timeout => write!(f, "no response from server"),
+            Self::Malformed(what) => write!(f, "malformed response: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for A2sError {}
+
+impl From<std::io::Error> for A2sError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Server details from `A2S_INFO`
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfo {
+    /// Server name
+    pub name: String,
+    /// Current map
+    pub map: String,
+    /// Game directory (e.g. "cstrike")
+    pub folder: String,
+    /// Game description
+    pub game: String,
+    /// Players on the server, including bots
+    pub players: u8,
+    /// Player slots
+    pub max_players: u8,
+    /// Bots on the server
+    pub bots: u8,
+    /// Whether a password is required to join
+    pub password: bool,
+    /// Whether the server is VAC secured
+    pub vac: bool,
+}
+
+/// Player entry from `A2S_PLAYER`
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerInfo {
+    /// Player name
+    pub name: String,
+    /// Frags
+    pub score: i32,
+    /// Seconds connected
+    pub duration_secs: f32,
+}
+
+/// Query `A2S_INFO` from a `host:port` address
+pub async fn info(address: &str, timeout: Duration) -> Result<ServerInfo, A2sError> {
+    let response = query(address, INFO_REQUEST, INFO_PAYLOAD, timeout).await?;
+    parse_info(&response)
+}
+
+/// Query `A2S_PLAYER` from a `host:port` address
+pub async fn players(address: &str, timeout: Duration) -> Result<Vec<PlayerInfo>, A2sError> {
+    let response = query(address, PLAYER_REQUEST, &NO_CHALLENGE, timeout).await?;
+    parse_players(&response)
+}
+
+/// Query `A2S_RULES` (server cvars) from a `host:port` address
+pub async fn rules(address: &str, timeout: Duration) -> Result<BTreeMap<String, String>, A2sError> {
+    let response = query(address, RULES_REQUEST, &NO_CHALLENGE, timeout).await?;
+    parse_rules(&response)
+}
+
+/// Send a query, answering a challenge if the server sends one
+///
+/// The challenge is appended to the info payload but replaces the
+/// [`NO_CHALLENGE`] placeholder of player and rules requests. Returns the
+/// response without the single-packet header.
+async fn query(
+    address: &str,
+    request: u8,
+    payload: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, A2sError> {
+    let target = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address found"))?;
+    let socket = UdpSocket::bind(if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    socket.connect(target).await?;
+
+    let mut packet = Vec::with_capacity(5 + payload.len() + 4);
+    packet.extend_from_slice(&SINGLE_PACKET);
+    packet.push(request);
+    packet.extend_from_slice(payload);
+
+    let exchange = async {
+        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+        socket.send(&packet).await?;
+        let mut response = receive(&socket, &mut buf).await?;
+
+        // Newer servers answer with a challenge that must be echoed back
+        if response.first() == Some(&CHALLENGE_RESPONSE) {
+            let challenge = response
+                .get(1..5)
+                .ok_or(A2sError::Malformed("short challenge"))?;
+            let keep = if payload == NO_CHALLENGE {
+                5
+            } else {
+                5 + payload.len()
+            };
+            packet.truncate(keep);
+            packet.extend_from_slice(challenge);
+            socket.send(&packet).await?;
+            response = receive(&socket, &mut buf).await?;
+        }
+
+        Ok(response)
+    };
+
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| A2sError::Timeout)?
+}
+
+/// Receive a response, reassembling split packets, and strip its header
+async fn receive(socket: &UdpSocket, buf: &mut [u8]) -> Result<Vec<u8>, A2sError> {
+    let n = socket.recv(buf).await?;
+    if let Some(single) = buf[..n].strip_prefix(&SINGLE_PACKET) {
+        return Ok(single.to_vec());
+    }
+    if !buf[..n].starts_with(&MULTI_PACKET) {
+        return Err(A2sError::Malformed("unexpected packet header"));
+    }
+
+    // `GoldSrc` split packet: header, i32 id, then total in the low and the
+    // index in the high nibble of one byte
+    let mut parts: Vec<Option<Vec<u8>>> = Vec::new();
+    let mut id = None;
+    let mut len = n;
+    loop {
+        let packet = &buf[..len];
+        let header = packet
+            .get(4..9)
+            .ok_or(A2sError::Malformed("short split packet"))?;
+        let packet_id = &header[..4];
+        let total = usize::from(header[4] & 0x0F);
+        let index = usize::from(header[4] >> 4);
+        if total == 0 || total > MAX_PACKETS || index >= total {
+            return Err(A2sError::Malformed("invalid split packet numbering"));
+        }
+
+        if *id.get_or_insert_with(|| packet_id.to_vec()) == packet_id {
+            parts.resize(total, None);
+            parts[index] = Some(packet[9..].to_vec());
+        }
+        if parts.iter().all(Option::is_some) {
+            break;
+        }
+
+        len = socket.recv(buf).await?;
+        if !buf[..len].starts_with(&MULTI_PACKET) {
+            return Err(A2sError::Malformed("unexpected packet header"));
+        }
+    }
+
+    let joined: Vec<u8> = parts.into_iter().flatten().flatten().collect();
+    joined
+        .strip_prefix(&SINGLE_PACKET)
+        .map(<[u8]>::to_vec)
+        .ok_or(A2sError::Malformed("unexpected packet header"))
+}
+
+/// Parse an `A2S_INFO` response in either format
+fn parse_info(response: &[u8]) -> Result<ServerInfo, A2sError> {
+    let mut r = Reader(response);
+    match r.u8()? {
+        INFO_RESPONSE => {
+            let _protocol = r.u8()?;
+            let name = r.string()?;
+            let map = r.string()?;
+            let folder = r.string()?;
+            let game = r.string()?;
+            let _app_id = r.u16()?;
+            let players = r.u8()?;
+            let max_players = r.u8()?;
+            let bots = r.u8()?;
+            let _server_type = r.u8()?;
+            let _environment = r.u8()?;
+            let password = r.u8()? != 0;
+            let vac = r.u8()? != 0;
+            Ok(ServerInfo {
+                name,
+                map,
+                folder,
+                game,
+                players,
+                max_players,
+                bots,
+                password,
+                vac,
+            })
+        }
+        GOLDSRC_INFO_RESPONSE => {
+            let _address = r.string()?;
+            let name = r.string()?;
+            let map = r.string()?;
+            let folder = r.string()?;
+            let game = r.string()?;
+            let players = r.u8()?;
+            let max_players = r.u8()?;
+            let _protocol = r.u8()?;
+            let _server_type = r.u8()?;
+            let _environment = r.u8()?;
+            let password = r.u8()? != 0;
+            if r.u8()? == 1 {
+                // Mod details: link, download link, null, version, size, type, dll
+                r.string()?;
+                r.string()?;
+                r.skip(1 + 4 + 4 + 1 + 1)?;
+            }
+            let vac = r.u8()? != 0;
+            let bots = r.u8()?;
+            Ok(ServerInfo {
+                name,
+                map,
+                folder,
+                game,
+                players,
+                max_players,
+                bots,
+                password,
+                vac,
+            })
+        }
+        _ => Err(A2sError::Malformed("not an info response")),
+    }
+}
+
+/// Parse an `A2S_PLAYER` response
+fn parse_players(response: &[u8]) -> Result<Vec<PlayerInfo>, A2sError> {
+    let mut r = Reader(response);
+    if r.u8()? != PLAYER_RESPONSE {
+        return Err(A2sError::Malformed("not a player response"));
+    }
+
+    let count = r.u8()?;
+    let mut players = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let _index = r.u8()?;
+        players.push(PlayerInfo {
+            name: r.string()?,
+            score: r.i32()?,
+            duration_secs: r.f32()?,
+        });
+    }
+    Ok(players)
+}
+
+/// Parse an `A2S_RULES` response
+fn parse_rules(response: &[u8]) -> Result<BTreeMap<String, String>, A2sError> {
+    let mut r = Reader(response);
+    if r.u8()? != RULES_RESPONSE {
+        return Err(A2sError::Malformed("not a rules response"));
+    }
+
+    let count = r.u16()?;
+    let mut rules = BTreeMap::new();
+    for _ in 0..count {
+        let name = r.string()?;
+        let value = r.string()?;
+        rules.insert(name, value);
+    }
+    Ok(rules)
+}
+
+/// Little-endian cursor over a response
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], A2sError> {
+        if self.0.len() < n {
+            return Err(A2sError::Malformed("truncated response"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), A2sError> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, A2sError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, A2sError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, A2sError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, A2sError> {
+        let bytes = self.take(4)?;
+        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Null-terminated string, decoded lossily
+    fn string(&mut self) -> Result<String, A2sError> {
+        let end = self
+            .0
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(A2sError::Malformed("unterminated string"))?;
+        let s = String::from_utf8_lossy(&self.0[..end]).into_owned();
+        self.0 = &self.0[end + 1..];
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Source `A2S_INFO` response without the single-packet header
+    fn source_info() -> Vec<u8> {
+        let mut response = vec![INFO_RESPONSE, 48];
+        response.extend_from_slice(b"My Server\0de_dust2\0cstrike\0Counter-Strike\0");
+        response.extend_from_slice(&10u16.to_le_bytes());
+        response.extend_from_slice(&[5, 32, 1, b'd', b'l', 0, 1]);
+        response.extend_from_slice(b"1.1.2.7\0");
+        response
+    }
+
+    #[test]
+    fn parses_source_info() {
+        let info = parse_info(&source_info()).unwrap();
+        assert_eq!(info.name, "My Server");
+        assert_eq!(info.map, "de_dust2");
+        assert_eq!(info.folder, "cstrike");
+        assert_eq!(info.game, "Counter-Strike");
+        assert_eq!((info.players, info.max_players, info.bots), (5, 32, 1));
+        assert!(!info.password);
+        assert!(info.vac);
+    }
+
+    #[test]
+    fn parses_goldsrc_info_with_mod_details() {
+        let mut response = vec![GOLDSRC_INFO_RESPONSE];
+        response.extend_from_slice(b"10.0.0.5:27015\0Old Server\0crossfire\0valve\0Half-Life\0");
+        response.extend_from_slice(&[3, 16, 47, b'd', b'l', 1, 1]);
+        response.extend_from_slice(b"http://mod\0http://dl\0");
+        // Null byte, version, size, type and dll of the mod
+        response.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 1]);
+        response.extend_from_slice(&[0, 2]);
+
+        let info = parse_info(&response).unwrap();
+        assert_eq!(info.name, "Old Server");
+        assert_eq!(info.map, "crossfire");
+        assert_eq!(info.folder, "valve");
+        assert_eq!((info.players, info.max_players, info.bots), (3, 16, 2));
+        assert!(info.password);
+        assert!(!info.vac);
+    }
+
+    #[test]
+    fn rejects_malformed_info() {
+        let info = source_info();
+        assert!(matches!(
+            parse_info(&info[..info.len() - 12]),
+            Err(A2sError::Malformed(_))
+        ));
+        assert!(parse_info(b"Iname without terminator").is_err());
+        assert!(parse_info(&[PLAYER_RESPONSE, 0]).is_err());
+        assert!(parse_info(&[]).is_err());
+    }
+
+    #[test]
+    fn parses_players_and_rules() {
+        let mut response = vec![PLAYER_RESPONSE, 2];
+        response.push(0);
+        response.extend_from_slice(b"alice\0");
+        response.extend_from_slice(&12i32.to_le_bytes());
+        response.extend_from_slice(&61.5f32.to_le_bytes());
+        response.push(1);
+        response.extend_from_slice(b"bob\0");
+        response.extend_from_slice(&(-1i32).to_le_bytes());
+        response.extend_from_slice(&2.0f32.to_le_bytes());
+
+        let players = parse_players(&response).unwrap();
+        assert_eq!(players.len(), 2);
+        assert_eq!((players[0].name.as_str(), players[0].score), ("alice", 12));
+        assert!((players[0].duration_secs - 61.5).abs() < f32::EPSILON);
+        assert_eq!((players[1].name.as_str(), players[1].score), ("bob", -1));
+        assert!(parse_players(&response[..response.len() - 1]).is_err());
+
+        let mut response = vec![RULES_RESPONSE];
+        response.extend_from_slice(&2u16.to_le_bytes());
+        response.extend_from_slice(b"mp_timelimit\x0020\0sv_gravity\0800\0");
+        let rules = parse_rules(&response).unwrap();
+        assert_eq!(rules["mp_timelimit"], "20");
+        assert_eq!(rules["sv_gravity"], "800");
+        assert!(parse_rules(&response[..response.len() - 4]).is_err());
+    }
+
+    /// Split a single-packet response into `GoldSrc` split packets
+    fn split(id: [u8; 4], response: &[u8], parts: usize) -> Vec<Vec<u8>> {
+        let mut payload = SINGLE_PACKET.to_vec();
+        payload.extend_from_slice(response);
+        let total = u8::try_from(parts).unwrap();
+        payload
+            .chunks(payload.len().div_ceil(parts))
+            .zip(0u8..)
+            .map(|(chunk, index)| {
+                let mut packet = MULTI_PACKET.to_vec();
+                packet.extend_from_slice(&id);
+                packet.push(index << 4 | total);
+                packet.extend_from_slice(chunk);
+                packet
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn answers_challenges_and_reassembles_split_responses() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+
+        let mut rules_response = vec![RULES_RESPONSE];
+        rules_response.extend_from_slice(&3u16.to_le_bytes());
+        rules_response.extend_from_slice(b"a\0x\0b\0y\0c\0z\0");
+
+        let expected = rules_response.clone();
+        let fake = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let (n, client) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"\xFF\xFF\xFF\xFFV\xFF\xFF\xFF\xFF");
+            server
+                .send_to(b"\xFF\xFF\xFF\xFFA\x01\x02\x03\x04", client)
+                .await
+                .unwrap();
+
+            let (n, _) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"\xFF\xFF\xFF\xFFV\x01\x02\x03\x04");
+
+            // Out of order, with a stray part of another response in between
+            let parts = split([7, 0, 0, 0], &expected, 3);
+            let stray = split([8, 0, 0, 0], b"E\0\0", 2);
+            for packet in [&parts[2], &stray[0], &parts[0], &parts[1]] {
+                server.send_to(packet, client).await.unwrap();
+            }
+        });
+
+        let rules = rules(&address, Duration::from_secs(2)).await.unwrap();
+        fake.await.unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules["c"], "z");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_split_numbering() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let (_, client) = server.recv_from(&mut buf).await.unwrap();
+            let mut packet = MULTI_PACKET.to_vec();
+            packet.extend_from_slice(&[1, 0, 0, 0, 0x22]);
+            server.send_to(&packet, client).await.unwrap();
+        });
+
+        assert!(matches!(
+            info(&address, Duration::from_secs(2)).await,
+            Err(A2sError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_without_an_answer() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        assert!(matches!(
+            info(&address, Duration::from_millis(50)).await,
+            Err(A2sError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+//! Admin REST API for live sessions.
+//!
+//! Always mounted under `/admin`, but answers `404` while no `--admin-token`
+//! is set, so a configuration reload can enable, disable or rotate the token.
+//! Every request must carry `Authorization: Bearer <token>`.
+
+use axum::body::Body;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, Response, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use tracing::info;
+
+use crate::auth::constant_time_eq;
+use crate::state::AppState;
+
+/// Admin routes
+pub fn router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:client_id", delete(disconnect_session))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+}
+
+/// Reject requests without the bearer token of the current settings
+async fn require_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let settings = state.settings();
+    let expected = match settings.config.admin_token.as_deref() {
+        Some(token) if !token.is_empty() => token,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .body(Body::from("Invalid admin token"))
+            .expect("building response should not fail");
+    }
+
+    next.run(request).await
+}
+
+/// `GET /admin/sessions`
+async fn list_sessions(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.sessions.list().await)
+}
+
+/// `DELETE /admin/sessions/<client_id>`
+async fn disconnect_session(
+    Path(client_id): Path<String>,
+    State(state): State<AppState>,
+) -> StatusCode {
+    if state.sessions.disconnect(&client_id) {
+        info!(client_id = %client_id, "Admin disconnected session");
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
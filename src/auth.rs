@@ -0,0 +1,334 @@
+//! Player authentication before signaling.
+//!
+//! With `--auth-password`, `--auth-jwt-secret` or `--auth-callback` set, a
+//! session must present a credential before the proxy allocates anything for
+//! it: as `?auth=` on `/ws` and `/webtransport`, or as `auth` in the client
+//! `hello`. The configured methods are tried in that order and the first one
+//! accepting the credential names the identity shown in logs and the admin API.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::config::Config;
+
+/// How long the verification callback may take
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why a session was not authenticated
+#[derive(Debug, Clone, Copy)]
+pub enum AuthError {
+    /// No credential was presented
+    Missing,
+    /// No method accepted the credential
+    Rejected,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "Authentication required"),
+            Self::Rejected => write!(f, "Invalid credentials"),
+        }
+    }
+}
+
+/// Who is asking, passed to every method
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AuthRequest<'a> {
+    /// Browser IP
+    pub client_ip: IpAddr,
+    /// Name of the server the session wants to play on
+    pub server: &'a str,
+}
+
+/// One way of checking a credential
+#[async_trait]
+pub trait AuthMethod: Send + Sync {
+    /// Identity behind the credential, `None` if this method rejects it
+    async fn verify(&self, credential: &str, request: AuthRequest<'_>) -> Option<String>;
+}
+
+/// Configured authentication methods
+pub struct Authenticator {
+    methods: Vec<Box<dyn AuthMethod>>,
+}
+
+impl Authenticator {
+    /// Build the methods enabled on the command line
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut methods: Vec<Box<dyn AuthMethod>> = Vec::new();
+        if let Some(password) = &config.auth_password {
+            methods.push(Box::new(PasswordAuth {
+                password: password.clone(),
+            }));
+        }
+        if let Some(secret) = &config.auth_jwt_secret {
+            methods.push(Box::new(JwtAuth {
+                secret: secret.clone().into_bytes(),
+            }));
+        }
+        if let Some(url) = &config.auth_callback {
+            let url = reqwest::Url::parse(url)
+                .map_err(|e| format!("Invalid auth callback URL '{url}': {e}"))?;
+            let client = reqwest::Client::builder()
+                .timeout(CALLBACK_TIMEOUT)
+                .build()?;
+            methods.push(Box::new(CallbackAuth { url, client }));
+        }
+        Ok(Self { methods })
+    }
+
+    /// Whether sessions need a credential at all
+    pub fn is_enabled(&self) -> bool {
+        !self.methods.is_empty()
+    }
+
+    /// Check a credential; `Ok(None)` when authentication is disabled
+    pub async fn authenticate(
+        &self,
+        credential: Option<&str>,
+        request: AuthRequest<'_>,
+    ) -> Result<Option<String>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let credential = credential
+            .filter(|c| !c.is_empty())
+            .ok_or(AuthError::Missing)?;
+
+        for method in &self.methods {
+            if let Some(identity) = method.verify(credential, request).await {
+                return Ok(Some(identity));
+            }
+        }
+        Err(AuthError::Rejected)
+    }
+}
+
+/// Shared password; every player is known as `password`
+struct PasswordAuth {
+    password: String,
+}
+
+#[async_trait]
+impl AuthMethod for PasswordAuth {
+    async fn verify(&self, credential: &str, _request: AuthRequest<'_>) -> Option<String> {
+        constant_time_eq(credential.as_bytes(), self.password.as_bytes())
+            .then(|| "password".to_string())
+    }
+}
+
+/// HS256 JSON Web Tokens; the identity is the `sub` or `name` claim
+struct JwtAuth {
+    secret: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    sub: Option<String>,
+    name: Option<String>,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    /// Servers the token is valid for, all if absent
+    servers: Option<Vec<String>>,
+}
+
+#[async_trait]
+impl AuthMethod for JwtAuth {
+    async fn verify(&self, credential: &str, request: AuthRequest<'_>) -> Option<String> {
+        let (signed, signature) = credential.rsplit_once('.')?;
+        let (header, claims) = signed.split_once('.')?;
+
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let header: JwtHeader = serde_json::from_slice(&b64.decode(header).ok()?).ok()?;
+        if header.alg != "HS256" {
+            return None;
+        }
+
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(signed.as_bytes());
+        mac.verify_slice(&b64.decode(signature).ok()?).ok()?;
+
+        let claims: JwtClaims = serde_json::from_slice(&b64.decode(claims).ok()?).ok()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        if claims.exp.is_some_and(|exp| now >= exp) || claims.nbf.is_some_and(|nbf| now < nbf) {
+            return None;
+        }
+        if claims
+            .servers
+            .is_some_and(|servers| !servers.iter().any(|s| s == request.server))
+        {
+            return None;
+        }
+
+        Some(
+            claims
+                .sub
+                .or(claims.name)
+                .unwrap_or_else(|| "token".to_string()),
+        )
+    }
+}
+
+/// External HTTP verification
+///
+/// The credential, client IP and server are sent as a JSON `POST`; any `2xx`
+/// answer accepts the session, optionally naming it with `{"identity": "..."}`.
+struct CallbackAuth {
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct CallbackRequest<'a> {
+    credential: &'a str,
+    #[serde(flatten)]
+    request: AuthRequest<'a>,
+}
+
+#[derive(Deserialize)]
+struct CallbackResponse {
+    identity: Option<String>,
+}
+
+#[async_trait]
+impl AuthMethod for CallbackAuth {
+    async fn verify(&self, credential: &str, request: AuthRequest<'_>) -> Option<String> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .json(&CallbackRequest {
+                credential,
+                request,
+            })
+            .send()
+            .await
+            .inspect_err(|e| warn!(error = %e, "Auth callback failed"))
+            .ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+
+        // An empty or non-JSON body still accepts the session
+        let identity = response
+            .json::<CallbackResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.identity);
+        Some(identity.unwrap_or_else(|| "callback".to_string()))
+    }
+}
+
+/// Compare secrets without leaking the position of the first mismatch
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn token(header: &serde_json::Value, claims: &serde_json::Value, secret: &[u8]) -> String {
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let signed = format!(
+            "{}.{}",
+            b64.encode(header.to_string()),
+            b64.encode(claims.to_string())
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{signed}.{}", b64.encode(mac.finalize().into_bytes()))
+    }
+
+    fn hs256(claims: &serde_json::Value) -> String {
+        token(&json!({ "alg": "HS256", "typ": "JWT" }), claims, SECRET)
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    async fn verify(credential: &str) -> Option<String> {
+        let auth = JwtAuth {
+            secret: SECRET.to_vec(),
+        };
+        let request = AuthRequest {
+            client_ip: IpAddr::from([127, 0, 0, 1]),
+            server: "default",
+        };
+        auth.verify(credential, request).await
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_tokens() {
+        assert_eq!(
+            verify(&hs256(&json!({ "sub": "alice", "name": "Alice" }))).await,
+            Some("alice".to_string())
+        );
+        assert_eq!(
+            verify(&hs256(&json!({ "name": "Alice" }))).await,
+            Some("Alice".to_string())
+        );
+        assert_eq!(
+            verify(&hs256(&json!({
+                "exp": now() + 60,
+                "nbf": now() - 60,
+                "servers": ["other", "default"],
+            })))
+            .await,
+            Some("token".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_tokens() {
+        let valid = hs256(&json!({ "sub": "alice" }));
+        let (signed, signature) = valid.rsplit_once('.').unwrap();
+        let (header, _) = signed.split_once('.').unwrap();
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let tampered = format!("{header}.{}.{signature}", b64.encode(r#"{"sub":"bob"}"#));
+
+        for credential in [
+            token(
+                &json!({ "alg": "HS256" }),
+                &json!({ "sub": "alice" }),
+                b"wrong",
+            ),
+            token(
+                &json!({ "alg": "none" }),
+                &json!({ "sub": "alice" }),
+                SECRET,
+            ),
+            format!("{signed}."),
+            format!("{signed}.!!!"),
+            tampered,
+            hs256(&json!({ "exp": now() })),
+            hs256(&json!({ "nbf": now() + 60 })),
+            hs256(&json!({ "servers": ["other"] })),
+            "not a token".to_string(),
+        ] {
+            assert_eq!(verify(&credential).await, None, "{credential}");
+        }
+    }
+}
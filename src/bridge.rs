@@ -1,104 +1,267 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
-use tokio::sync::Notify;
-use tracing::{debug, error, info};
-use webrtc::data_channel::data_channel_message::DataChannelMessage;
-use webrtc::data_channel::RTCDataChannel;
+use tokio::sync::{mpsc, Notify};
+use tracing::{debug, error, info, warn};
+
+use crate::metrics::{DirectionMetrics, Metrics};
+use crate::protocol::{DirectionStats, SessionStats, TransportKind};
+use crate::rate_limit::{DirectionLimit, RateLimitPolicy, RateLimits, Throttle};
+use crate::target_policy::TargetPolicy;
+use crate::transport::ClientTransport;
 
 /// Maximum packet size for `GoldSrc` protocol
 const MAX_PACKET_SIZE: usize = 65536;
 
-/// Bridge between WebRTC data channels and UDP socket to game server
+/// How long the game server may stay silent after the first client packet
+const SERVER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimum time between two rate limit warnings of one direction
+const RATE_LIMIT_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Packet, byte and jitter counters and the rate limit of one bridge direction
+struct TrafficCounter {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    jitter: Mutex<JitterEstimator>,
+    throttle: Mutex<Throttle>,
+    /// Packets dropped by the rate limit
+    dropped: AtomicU64,
+    /// When packets over the limit were last logged
+    excess_logged_at: Mutex<Option<Instant>>,
+}
+
+impl TrafficCounter {
+    fn new(limit: DirectionLimit) -> Self {
+        Self {
+            packets: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            jitter: Mutex::new(JitterEstimator::default()),
+            throttle: Mutex::new(Throttle::new(limit)),
+            dropped: AtomicU64::new(0),
+            excess_logged_at: Mutex::new(None),
+        }
+    }
+
+    /// Whether a packet is within the rate limit
+    fn admit(&self, bytes: usize) -> bool {
+        self.throttle
+            .lock()
+            .expect("throttle lock poisoned")
+            .admit(bytes)
+    }
+
+    /// Whether packets over the limit should be logged again
+    fn should_log_excess(&self) -> bool {
+        let mut logged_at = self.excess_logged_at.lock().expect("log lock poisoned");
+        if logged_at.is_some_and(|t| t.elapsed() < RATE_LIMIT_LOG_INTERVAL) {
+            return false;
+        }
+        *logged_at = Some(Instant::now());
+        true
+    }
+
+    /// Count one forwarded packet arriving now
+    fn record(&self, bytes: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.jitter
+            .lock()
+            .expect("jitter lock poisoned")
+            .update(Instant::now());
+    }
+
+    fn snapshot(&self) -> DirectionStats {
+        DirectionStats {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            jitter_ms: self.jitter.lock().expect("jitter lock poisoned").jitter * 1000.0,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Inter-arrival jitter in the style of RFC 3550
 ///
-/// Uses two channels to match the original client expectations:
-/// - `write` channel: proxy sends TO browser (server → client)
-/// - `read` channel: proxy receives FROM browser (client → server)
+/// Game packets carry no sender timestamps, so the transit time difference is
+/// approximated by how much consecutive inter-arrival intervals differ.
+#[derive(Default)]
+struct JitterEstimator {
+    last_arrival: Option<Instant>,
+    last_interval: Option<Duration>,
+    /// Smoothed jitter in seconds
+    jitter: f64,
+}
+
+impl JitterEstimator {
+    fn update(&mut self, now: Instant) {
+        if let Some(last) = self.last_arrival {
+            let interval = now - last;
+            if let Some(previous) = self.last_interval {
+                let d = (interval.as_secs_f64() - previous.as_secs_f64()).abs();
+                self.jitter += (d - self.jitter) / 16.0;
+            }
+            self.last_interval = Some(interval);
+        }
+        self.last_arrival = Some(now);
+    }
+}
+
+/// Status events reported by a running bridge
+#[derive(Debug)]
+pub enum BridgeEvent {
+    /// The game server refused packets or has not answered in time
+    ServerUnreachable(String),
+    /// The session exceeded its rate limit and the bridge shut down
+    RateLimited(String),
+}
+
+/// Bridge between a browser transport and UDP socket to game server
+///
+/// The browser side is any [`ClientTransport`]: WebRTC data channels or the
+/// WebSocket fallback.
 pub struct Bridge {
-    /// Channel for sending data TO the browser (server responses)
-    write_channel: Arc<RTCDataChannel>,
-    /// Channel for receiving data FROM the browser (client commands)
-    read_channel: Arc<RTCDataChannel>,
+    /// Packet transport to and from the browser
+    transport: Arc<dyn ClientTransport>,
     /// UDP socket connected to game server
     udp_socket: Arc<UdpSocket>,
+    /// Local port of the UDP socket
+    local_port: u16,
     /// Shutdown signal
     shutdown: Arc<Notify>,
     /// Client identifier for logging
     client_id: String,
+    /// Status events for the signaling layer
+    events: mpsc::UnboundedSender<BridgeEvent>,
+    /// When the first packet was forwarded to the game server
+    first_sent_at: OnceLock<Instant>,
+    /// Whether the game server has answered since the last unreachable report
+    server_responded: AtomicBool,
+    /// Whether an unreachable event was already reported
+    unreachable_reported: AtomicBool,
+    /// Browser → game server traffic of this session
+    to_server: TrafficCounter,
+    /// Game server → browser traffic of this session
+    to_client: TrafficCounter,
+    /// What happens to packets over the rate limit
+    rate_limit_policy: RateLimitPolicy,
+    /// Whether the session was cut off for exceeding its rate limit
+    rate_limited: AtomicBool,
+    /// Packet counters and the active bridge gauge
+    metrics: Arc<Metrics>,
 }
 
 impl Bridge {
-    /// Create a new bridge connecting data channels to a game server
+    /// Create a new bridge connecting a browser transport to a game server
+    ///
+    /// The server address must pass the target policy; a refusal is returned
+    /// as [`crate::target_policy::TargetRefused`].
     pub async fn new(
-        write_channel: Arc<RTCDataChannel>,
-        read_channel: Arc<RTCDataChannel>,
+        transport: Arc<dyn ClientTransport>,
         server_addr: &str,
+        policy: &TargetPolicy,
         client_id: String,
+        events: mpsc::UnboundedSender<BridgeEvent>,
+        metrics: Arc<Metrics>,
+        rate_limits: RateLimits,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        // Bind to random local port
-        let udp_socket = UdpSocket::bind("0.0.0.0:0").await?;
+        // Resolve and check the target before any socket is created
+        let target = policy.resolve(server_addr).await?;
+
+        // Bind to random local port of the same address family
+        let bind_addr = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let udp_socket = UdpSocket::bind(bind_addr).await?;
 
         // Connect to game server (allows us to use send/recv instead of send_to/recv_from)
-        udp_socket.connect(server_addr).await?;
+        // Connect to the verified IP, never re-resolving the hostname
+        udp_socket.connect(target).await?;
 
         let local_addr = udp_socket.local_addr()?;
         info!(
             client_id = %client_id,
             local_port = %local_addr.port(),
             server = %server_addr,
+            target = %target,
+            transport = ?transport.kind(),
             "UDP socket connected to game server"
         );
 
+        metrics.bridges(transport.kind()).inc();
+
         Ok(Self {
-            write_channel,
-            read_channel,
+            transport,
             udp_socket: Arc::new(udp_socket),
+            local_port: local_addr.port(),
             shutdown: Arc::new(Notify::new()),
             client_id,
+            events,
+            first_sent_at: OnceLock::new(),
+            server_responded: AtomicBool::new(false),
+            unreachable_reported: AtomicBool::new(false),
+            to_server: TrafficCounter::new(rate_limits.to_server),
+            to_client: TrafficCounter::new(rate_limits.to_client),
+            rate_limit_policy: rate_limits.policy,
+            rate_limited: AtomicBool::new(false),
+            metrics,
         })
     }
 
     /// Start bidirectional forwarding
     pub async fn start(self: Arc<Self>) {
-        // Spawn UDP → WebRTC forwarder (server responses to browser via write channel)
-        let udp_to_webrtc = tokio::spawn({
+        // Spawn UDP → browser forwarder (server responses)
+        let udp_to_client = tokio::spawn({
             let bridge = self.clone();
             async move {
-                bridge.forward_udp_to_webrtc().await;
+                bridge.forward_udp_to_client().await;
             }
         });
 
-        // Setup WebRTC → UDP forwarder (browser commands to server via read channel)
-        self.setup_webrtc_to_udp();
+        // Browser → UDP forwarding (player commands)
+        self.transport.attach(Arc::downgrade(&self));
 
         // Wait for shutdown signal
         self.shutdown.notified().await;
 
         // Cleanup
-        udp_to_webrtc.abort();
+        udp_to_client.abort();
         info!(client_id = %self.client_id, "Bridge shut down");
     }
 
-    /// Forward packets from UDP (game server) to WebRTC write channel (browser)
-    async fn forward_udp_to_webrtc(&self) {
+    /// Forward packets from UDP (game server) to the browser transport
+    async fn forward_udp_to_client(&self) {
         let mut buf = vec![0u8; MAX_PACKET_SIZE];
+        let mut response_check = tokio::time::interval(Duration::from_secs(1));
 
         loop {
             tokio::select! {
                 result = self.udp_socket.recv(&mut buf) => {
                     match result {
                         Ok(n) if n > 0 => {
+                            self.server_responded.store(true, Ordering::Relaxed);
+                            self.unreachable_reported.store(false, Ordering::Relaxed);
+                            if !self.admit(&self.to_client, &self.metrics.to_client, n, "server → browser") {
+                                continue;
+                            }
                             let data = bytes::Bytes::copy_from_slice(&buf[..n]);
                             debug!(
                                 client_id = %self.client_id,
                                 bytes = n,
-                                "UDP → WebRTC (write channel)"
+                                "UDP → browser"
                             );
 
-                            if let Err(e) = self.write_channel.send(&data).await {
+                            self.metrics.to_client.record(n);
+                            self.to_client.record(n);
+                            if let Err(e) = self.transport.send(&data).await {
+                                self.metrics.to_client.send_errors.inc();
                                 error!(
                                     client_id = %self.client_id,
                                     error = %e,
-                                    "Failed to send to write channel"
+                                    "Failed to send to browser"
                                 );
                                 break;
                             }
@@ -106,6 +269,11 @@ impl Bridge {
                         Ok(_) => {
                             // Empty packet, continue
                         }
+                        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                            // ICMP port unreachable from a previous send; the
+                            // server may come back, so keep the bridge alive
+                            self.report_unreachable("game server refused the connection");
+                        }
                         Err(e) => {
                             error!(
                                 client_id = %self.client_id,
@@ -116,6 +284,16 @@ impl Bridge {
                         }
                     }
                 }
+                _ = response_check.tick() => {
+                    let silent = !self.server_responded.load(Ordering::Relaxed);
+                    let waited = self
+                        .first_sent_at
+                        .get()
+                        .is_some_and(|t| t.elapsed() >= SERVER_RESPONSE_TIMEOUT);
+                    if silent && waited {
+                        self.report_unreachable("game server did not respond");
+                    }
+                }
                 () = self.shutdown.notified() => {
                     break;
                 }
@@ -123,53 +301,113 @@ impl Bridge {
         }
     }
 
-    /// Setup callback for WebRTC read channel → UDP forwarding (browser to game server)
-    fn setup_webrtc_to_udp(&self) {
-        let udp_socket = self.udp_socket.clone();
-        let client_id = self.client_id.clone();
-        let shutdown = self.shutdown.clone();
-
-        // Handle incoming messages on the read channel
-        self.read_channel
-            .on_message(Box::new(move |msg: DataChannelMessage| {
-                let udp_socket = udp_socket.clone();
-                let client_id = client_id.clone();
-
-                Box::pin(async move {
-                    let data = msg.data;
-                    debug!(
-                        client_id = %client_id,
-                        bytes = data.len(),
-                        "WebRTC (read channel) → UDP"
-                    );
-
-                    if let Err(e) = udp_socket.send(&data).await {
-                        error!(
-                            client_id = %client_id,
-                            error = %e,
-                            "Failed to send to UDP"
-                        );
-                    }
-                })
-            }));
-
-        // Handle read channel close
-        let shutdown_clone = shutdown.clone();
-        let client_id = self.client_id.clone();
-        self.read_channel.on_close(Box::new(move || {
-            info!(client_id = %client_id, "Read channel closed");
-            shutdown_clone.notify_one();
-            Box::pin(async {})
-        }));
-
-        // Handle read channel errors
-        let shutdown_clone = shutdown;
-        let client_id = self.client_id.clone();
-        self.read_channel.on_error(Box::new(move |e| {
-            error!(client_id = %client_id, error = %e, "Read channel error");
-            shutdown_clone.notify_one();
-            Box::pin(async {})
-        }));
+    /// Report the game server as unreachable, once until it answers again
+    fn report_unreachable(&self, reason: &str) {
+        self.server_responded.store(false, Ordering::Relaxed);
+        if self.unreachable_reported.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        warn!(client_id = %self.client_id, reason, "Game server unreachable");
+        let _ = self
+            .events
+            .send(BridgeEvent::ServerUnreachable(reason.to_string()));
+    }
+
+    /// Check a packet against the rate limit of its direction and apply the policy
+    fn admit(
+        &self,
+        counter: &TrafficCounter,
+        metrics: &DirectionMetrics,
+        bytes: usize,
+        direction: &str,
+    ) -> bool {
+        if self.rate_limited.load(Ordering::Relaxed) {
+            return false;
+        }
+        if counter.admit(bytes) {
+            return true;
+        }
+
+        if self.rate_limit_policy == RateLimitPolicy::Log {
+            if counter.should_log_excess() {
+                warn!(client_id = %self.client_id, direction, "Rate limit exceeded");
+            }
+            return true;
+        }
+
+        counter.dropped.fetch_add(1, Ordering::Relaxed);
+        metrics.dropped.inc();
+        match self.rate_limit_policy {
+            RateLimitPolicy::Disconnect => {
+                if !self.rate_limited.swap(true, Ordering::Relaxed) {
+                    warn!(client_id = %self.client_id, direction, "Rate limit exceeded, disconnecting");
+                    let _ = self.events.send(BridgeEvent::RateLimited(format!(
+                        "rate limit exceeded ({direction})"
+                    )));
+                    self.shutdown();
+                }
+            }
+            _ => {
+                if counter.should_log_excess() {
+                    warn!(client_id = %self.client_id, direction, "Rate limit exceeded, dropping packets");
+                }
+            }
+        }
+        false
+    }
+
+    /// Forward a packet from the browser to the game server
+    pub async fn forward_to_server(&self, data: &[u8]) {
+        if !self.admit(
+            &self.to_server,
+            &self.metrics.to_server,
+            data.len(),
+            "browser → server",
+        ) {
+            return;
+        }
+        self.first_sent_at.get_or_init(Instant::now);
+        debug!(
+            client_id = %self.client_id,
+            bytes = data.len(),
+            "Browser → UDP"
+        );
+
+        self.metrics.to_server.record(data.len());
+        self.to_server.record(data.len());
+        if let Err(e) = self.udp_socket.send(data).await {
+            self.metrics.to_server.send_errors.inc();
+            error!(
+                client_id = %self.client_id,
+                error = %e,
+                "Failed to send to UDP"
+            );
+        }
+    }
+
+    /// Transport the bridge forwards over
+    pub fn transport_kind(&self) -> TransportKind {
+        self.transport.kind()
+    }
+
+    /// Local UDP port towards the game server
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// Traffic statistics since the bridge started
+    pub async fn stats(&self) -> SessionStats {
+        SessionStats {
+            to_server: self.to_server.snapshot(),
+            to_client: self.to_client.snapshot(),
+            buffered_amount: self.transport.buffered_amount().await,
+        }
+    }
+
+    /// Client identifier for logging
+    pub fn client_id(&self) -> &str {
+        &self.client_id
     }
 
     /// Shutdown the bridge
@@ -180,6 +418,7 @@ impl Bridge {
 
 impl Drop for Bridge {
     fn drop(&mut self) {
+        self.metrics.bridges(self.transport.kind()).dec();
         info!(client_id = %self.client_id, "Bridge dropped");
     }
 }
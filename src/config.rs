@@ -1,15 +1,74 @@
 //! CLI configuration and argument parsing.
+//!
+//! Settings can also come from a TOML file (`--config`). Its keys are the long
+//! option names (`max_sessions` or `max-sessions`), plus structured settings
+//! the command line cannot express: `[[servers]]`, `[[ice_servers]]` and
+//! `[files_map]`. Options given on the command line or in the environment
+//! take precedence over the file.
 
-use clap::Parser;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
+
+use crate::ice::IceServer;
+use crate::protocol::ChannelMode;
+use crate::rate_limit::RateLimitPolicy;
+use crate::servers::ServerEntry;
+
+/// Settings only the configuration file can set
+const FILE_SETTINGS: [&str; 3] = ["servers", "ice_servers", "files_map"];
+
+/// Settings holding passwords, tokens and shared secrets
+///
+/// `ice_servers` entries may also carry a TURN `credential`.
+pub const SECRET_SETTINGS: [&str; 5] = [
+    "turn_credential",
+    "turn_secret",
+    "auth_password",
+    "auth_jwt_secret",
+    "admin_token",
+];
+
+/// Options that only control the current invocation
+const INVOCATION_OPTIONS: [&str; 3] = ["settings_file", "print_effective", "show_secrets"];
 
 /// WebRTC to UDP proxy for CS 1.6 / Half-Life servers
-#[derive(Parser, Debug, Clone)]
+// Independent CLI flags
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
 #[command(name = "webxash3d-proxy")]
 #[command(about = "WebRTC to UDP proxy for CS 1.6 / Half-Life servers")]
 pub struct Config {
-    /// CS 1.6 server address (e.g., 192.168.1.100:27015)
-    #[arg(short, long, env = "GAME_SERVER")]
-    pub server: String,
+    /// TOML configuration file; command line and environment override its values
+    #[arg(long = "config", env = "CONFIG_FILE")]
+    #[serde(skip)]
+    pub settings_file: Option<String>,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long = "print-config")]
+    #[serde(skip)]
+    pub print_effective: bool,
+
+    /// Include passwords, tokens and secrets in `--print-config`
+    #[arg(long, requires = "print_effective")]
+    #[serde(skip)]
+    pub show_secrets: bool,
+
+    /// CS 1.6 server address (e.g., 192.168.1.100:27015), registered as the "default" server
+    #[arg(
+        short,
+        long,
+        env = "GAME_SERVER",
+        required_unless_present_any = ["servers_file", "settings_file"]
+    )]
+    pub server: Option<String>,
+
+    /// Path to a JSON file with additional named servers
+    #[arg(long, env = "SERVERS_FILE")]
+    pub servers_file: Option<String>,
 
     /// Port to listen for WebSocket/HTTP connections
     #[arg(short, long, default_value = "27016", env = "LISTEN_PORT")]
@@ -23,6 +82,213 @@ pub struct Config {
     #[arg(long, env = "PUBLIC_IP")]
     pub public_ip: Option<String>,
 
+    /// STUN server URLs (comma-separated, empty to disable)
+    #[arg(
+        long,
+        env = "STUN_SERVERS",
+        value_delimiter = ',',
+        default_value = "stun:stun.l.google.com:19302"
+    )]
+    pub stun_server: Vec<String>,
+
+    /// TURN server URLs (comma-separated, e.g. "turn:turn.example.com:3478?transport=udp")
+    #[arg(long, env = "TURN_SERVERS", value_delimiter = ',')]
+    pub turn_server: Vec<String>,
+
+    /// Static TURN username
+    #[arg(long, env = "TURN_USERNAME", requires = "turn_credential")]
+    pub turn_username: Option<String>,
+
+    /// Static TURN password
+    #[arg(long, env = "TURN_CREDENTIAL", requires = "turn_username")]
+    pub turn_credential: Option<String>,
+
+    /// Shared secret for time-limited TURN REST API credentials
+    #[arg(long, env = "TURN_SECRET", conflicts_with = "turn_credential")]
+    pub turn_secret: Option<String>,
+
+    /// Seconds generated TURN credentials admit new relay allocations
+    #[arg(long, default_value = "300", env = "TURN_TTL")]
+    pub turn_ttl: u64,
+
+    /// Run the embedded TURN server on this UDP and TCP port
+    #[arg(long, env = "TURN_LISTEN_PORT")]
+    pub turn_listen_port: Option<u16>,
+
+    /// Address the embedded TURN server advertises for relays (defaults to --public-ip)
+    #[arg(long, env = "TURN_RELAY_IP")]
+    pub turn_relay_ip: Option<String>,
+
+    /// Port range for embedded TURN relay sockets (e.g. "49160-49200")
+    #[arg(long, env = "TURN_RELAY_PORTS")]
+    pub turn_relay_ports: Option<String>,
+
+    /// Let embedded TURN relays reach private, loopback and link-local addresses
+    #[arg(long, env = "TURN_ALLOW_PRIVATE_PEERS")]
+    pub turn_allow_private_peers: bool,
+
+    /// Realm of the embedded TURN server
+    #[arg(long, default_value = "webxash3d", env = "TURN_REALM")]
+    pub turn_realm: String,
+
+    /// Default delivery mode of the game data channels
+    #[arg(
+        long,
+        value_enum,
+        default_value = "unreliable",
+        env = "DATA_CHANNEL_MODE"
+    )]
+    pub data_channel_mode: ChannelMode,
+
+    /// Retransmission window in milliseconds for the `lifetime` channel mode
+    #[arg(long, default_value = "150", env = "DATA_CHANNEL_LIFETIME")]
+    pub data_channel_lifetime: u16,
+
+    /// Share one UDP port between all peer connections (ICE UDP mux)
+    #[arg(long, env = "ICE_UDP_PORT")]
+    pub ice_udp_port: Option<u16>,
+
+    /// Restrict per-connection ICE UDP ports to a range (e.g. "50000-50100")
+    #[arg(long, env = "ICE_PORT_RANGE", conflicts_with = "ice_udp_port")]
+    pub ice_port_range: Option<String>,
+
+    /// PEM certificate chain to serve HTTPS/WSS with
+    #[arg(long, env = "TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key for `--tls-cert`
+    #[arg(long, env = "TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// Seconds between checks of `--tls-cert`/`--tls-key` for renewed files
+    #[arg(long, default_value = "30", env = "TLS_RELOAD_SECS")]
+    pub tls_reload_secs: u64,
+
+    /// Serve HTTPS/WSS with a certificate generated at startup (e.g. for LAN parties)
+    #[arg(long, env = "TLS_SELF_SIGNED", conflicts_with = "tls_cert")]
+    pub tls_self_signed: bool,
+
+    /// Extra hostnames or IPs of the self-signed certificate (comma-separated)
+    #[arg(long, env = "TLS_SELF_SIGNED_NAMES", value_delimiter = ',')]
+    pub tls_self_signed_name: Vec<String>,
+
+    /// Serve WebTransport (HTTP/3 datagrams) on this UDP port
+    #[arg(
+        long,
+        env = "WEBTRANSPORT_PORT",
+        requires_all = ["webtransport_cert", "webtransport_key"]
+    )]
+    pub webtransport_port: Option<u16>,
+
+    /// PEM certificate chain for WebTransport
+    #[arg(long, env = "WEBTRANSPORT_CERT")]
+    pub webtransport_cert: Option<String>,
+
+    /// PEM private key for WebTransport
+    #[arg(long, env = "WEBTRANSPORT_KEY")]
+    pub webtransport_key: Option<String>,
+
+    /// Seconds to reuse A2S answers on `/api/server/info` and `/health/ready`
+    #[arg(long, default_value = "5", env = "SERVER_INFO_CACHE_SECS")]
+    pub server_info_cache_secs: u64,
+
+    /// Valve master server listing servers for `/api/servers` (e.g. "hl1master.steampowered.com:27011")
+    #[arg(long, env = "MASTER_SERVER")]
+    pub master_server: Option<String>,
+
+    /// Master server filter string
+    #[arg(long, default_value = "\\gamedir\\cstrike", env = "MASTER_FILTER")]
+    pub master_filter: String,
+
+    /// Maximum number of servers taken from the master server
+    #[arg(long, default_value = "500", env = "MASTER_MAX_SERVERS")]
+    pub master_max_servers: usize,
+
+    /// JSON file with a list of `host:port` servers for `/api/servers`
+    #[arg(long, env = "SERVER_LIST_FILE")]
+    pub server_list_file: Option<String>,
+
+    /// Concurrent `A2S_INFO` queries while refreshing `/api/servers`
+    #[arg(long, default_value = "32", env = "SERVER_BROWSER_CONCURRENCY")]
+    pub server_browser_concurrency: usize,
+
+    /// Seconds between `/api/servers` refreshes
+    #[arg(long, default_value = "60", env = "SERVER_BROWSER_REFRESH_SECS")]
+    pub server_browser_refresh_secs: u64,
+
+    /// Timeout of the game server probes on `/health/ready` in milliseconds
+    #[arg(long, default_value = "1500", env = "HEALTH_PROBE_TIMEOUT_MS")]
+    pub health_probe_timeout_ms: u64,
+
+    /// Seconds to let sessions finish after SIGTERM before closing their bridges
+    #[arg(long, default_value = "30", env = "SHUTDOWN_TIMEOUT")]
+    pub shutdown_timeout: u64,
+
+    /// Seconds between `stats` events sent to the browser (0 = disabled)
+    #[arg(long, default_value = "2", env = "STATS_INTERVAL")]
+    pub stats_interval: u64,
+
+    /// Maximum concurrent sessions (0 = unlimited)
+    #[arg(long, default_value = "0", env = "MAX_SESSIONS")]
+    pub max_sessions: usize,
+
+    /// Maximum concurrent sessions per client IP (0 = unlimited)
+    #[arg(long, default_value = "0", env = "MAX_SESSIONS_PER_IP")]
+    pub max_sessions_per_ip: usize,
+
+    /// Reverse proxies whose client IP header is trusted (comma-separated CIDRs)
+    #[arg(long, env = "TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxy: Vec<String>,
+
+    /// Header a trusted reverse proxy puts the client IP in
+    #[arg(long, default_value = "x-forwarded-for", env = "CLIENT_IP_HEADER")]
+    pub client_ip_header: String,
+
+    /// Packets per second a session may send to the game server (0 = unlimited)
+    #[arg(long, default_value = "0", env = "TO_SERVER_PACKETS_PER_SEC")]
+    pub to_server_packets_per_sec: u64,
+
+    /// Bytes per second a session may send to the game server (0 = unlimited)
+    #[arg(long, default_value = "0", env = "TO_SERVER_BYTES_PER_SEC")]
+    pub to_server_bytes_per_sec: u64,
+
+    /// Packets per second the game server may send to a session (0 = unlimited)
+    #[arg(long, default_value = "0", env = "TO_CLIENT_PACKETS_PER_SEC")]
+    pub to_client_packets_per_sec: u64,
+
+    /// Bytes per second the game server may send to a session (0 = unlimited)
+    #[arg(long, default_value = "0", env = "TO_CLIENT_BYTES_PER_SEC")]
+    pub to_client_bytes_per_sec: u64,
+
+    /// What happens to packets over the rate limits
+    #[arg(long, value_enum, default_value = "drop", env = "RATE_LIMIT_POLICY")]
+    pub rate_limit_policy: RateLimitPolicy,
+
+    /// Websites allowed to use the proxy (comma-separated origins, "*" for any)
+    #[arg(
+        long,
+        default_value = "*",
+        env = "ALLOWED_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub allowed_origin: Vec<String>,
+
+    /// Shared password players must enter to join
+    #[arg(long, env = "AUTH_PASSWORD")]
+    pub auth_password: Option<String>,
+
+    /// Secret of HS256 JSON Web Tokens that admit players
+    #[arg(long, env = "AUTH_JWT_SECRET")]
+    pub auth_jwt_secret: Option<String>,
+
+    /// URL that verifies player credentials (JSON `POST`, any `2xx` admits)
+    #[arg(long, env = "AUTH_CALLBACK")]
+    pub auth_callback: Option<String>,
+
+    /// Bearer token for the `/admin` API (admin API disabled if unset)
+    #[arg(long, env = "ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
     /// Enable verbose logging
     #[arg(short, long)]
     pub verbose: bool,
@@ -35,16 +301,169 @@ pub struct Config {
     #[arg(long, env = "STATIC_DIR", hide = true)]
     pub static_dir: Option<String>,
 
-    /// Game directory name (e.g., "cstrike", "valve")
+    /// Game directory name of the default server (e.g., "cstrike", "valve")
     #[arg(long, default_value = "cstrike", env = "GAME_DIR")]
     pub game_dir: String,
 
-    /// Extra console commands to execute on client start (comma-separated)
-    #[arg(long, env = "CONSOLE_COMMANDS")]
-    pub console_commands: Option<String>,
+    /// Extra console commands for the default server (comma-separated)
+    #[arg(long, env = "CONSOLE_COMMANDS", value_delimiter = ',')]
+    pub console_commands: Vec<String>,
+
+    /// Let browsers connect to any `host:port` permitted by the target policy
+    #[arg(long, env = "ALLOW_CUSTOM_TARGETS")]
+    pub allow_custom_targets: bool,
+
+    /// Networks upstream targets may resolve into (comma-separated CIDRs)
+    #[arg(long, env = "TARGET_ALLOW_CIDRS", value_delimiter = ',')]
+    pub allow_target_cidr: Vec<String>,
+
+    /// Networks custom targets may never resolve into (comma-separated CIDRs)
+    #[arg(long, env = "TARGET_DENY_CIDRS", value_delimiter = ',')]
+    pub deny_target_cidr: Vec<String>,
+
+    /// Allowed upstream ports (comma-separated, e.g. "27015-27020,27030")
+    #[arg(long, env = "TARGET_ALLOW_PORTS", value_delimiter = ',')]
+    pub allow_target_ports: Vec<String>,
+
+    /// Upstream hostnames allowed regardless of CIDR rules (comma-separated)
+    #[arg(long, env = "TARGET_ALLOW_HOSTS", value_delimiter = ',')]
+    pub allow_target_host: Vec<String>,
+
+    /// Let custom targets resolve into loopback, unspecified and link-local addresses
+    #[arg(long, env = "TARGET_ALLOW_LOCAL")]
+    pub allow_local_targets: bool,
+
+    /// Seconds to reuse resolved target addresses (0 = resolve on every connection)
+    #[arg(long, default_value = "60", env = "TARGET_DNS_CACHE_SECS")]
+    pub target_dns_cache_secs: u64,
+
+    /// Named game servers from the configuration file
+    #[arg(skip)]
+    #[serde(default)]
+    pub servers: Vec<ServerEntry>,
+
+    /// Additional ICE servers from the configuration file
+    #[arg(skip)]
+    #[serde(default)]
+    pub ice_servers: Vec<IceServer>,
+
+    /// Additional `files_map` entries of every server from the configuration file
+    #[arg(skip)]
+    #[serde(default)]
+    pub files_map: BTreeMap<String, String>,
+}
+
+/// Parsed command line and environment
+///
+/// Kept for the lifetime of the process so a reload merges the `--config`
+/// file under the same options without parsing the command line again.
+#[derive(Debug, Clone)]
+pub struct CommandLine {
+    matches: ArgMatches,
+    config: Config,
+}
+
+impl CommandLine {
+    /// Parse the command line, exiting with usage on invalid options
+    pub fn parse() -> Result<Self, Box<dyn std::error::Error>> {
+        let matches = Config::command().get_matches();
+        let config = Config::from_arg_matches(&matches)?;
+        Ok(Self { matches, config })
+    }
+
+    /// Read the `--config` file and merge it under the command line
+    ///
+    /// This reads the file with blocking IO.
+    pub fn load(&self) -> Result<Config, Box<dyn std::error::Error>> {
+        let Some(path) = self.config.settings_file.clone() else {
+            return Ok(self.config.clone());
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read config file {path}: {e}"))?;
+        let file: toml::Table = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse config file {path}: {e}"))?;
+
+        let mut merged = self
+            .config
+            .merge_file(file, &self.matches)
+            .map_err(|e| format!("Invalid config file {path}: {e}"))?;
+        merged.settings_file = Some(path);
+        merged.print_effective = self.config.print_effective;
+        merged.show_secrets = self.config.show_secrets;
+        Ok(merged)
+    }
 }
 
 impl Config {
+    /// Take every file setting that was not given on the command line or in
+    /// the environment
+    fn merge_file(
+        &self,
+        file: toml::Table,
+        matches: &ArgMatches,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let options: Vec<String> = Self::command()
+            .get_arguments()
+            .map(|arg| arg.get_id().to_string())
+            .filter(|id| !INVOCATION_OPTIONS.contains(&id.as_str()))
+            .collect();
+
+        let toml::Value::Table(mut merged) = toml::Value::try_from(self)? else {
+            unreachable!("Config serializes to a table");
+        };
+        for (key, value) in file {
+            let key = key.replace('-', "_");
+            let overridden = if options.contains(&key) {
+                overrides_file(matches.value_source(&key))
+            } else if FILE_SETTINGS.contains(&key.as_str()) {
+                false
+            } else {
+                return Err(format!("unknown setting '{key}'").into());
+            };
+            if !overridden {
+                merged.insert(key, value);
+            }
+        }
+
+        Ok(merged.try_into()?)
+    }
+
+    /// Effective configuration as TOML, as printed by `--print-config`
+    ///
+    /// Secrets are left out and listed in a comment instead, unless
+    /// `--show-secrets` is given.
+    pub fn to_toml(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let toml::Value::Table(mut table) = toml::Value::try_from(self)? else {
+            unreachable!("Config serializes to a table");
+        };
+        if self.show_secrets {
+            return Ok(toml::to_string_pretty(&table)?);
+        }
+
+        let mut redacted: Vec<String> = SECRET_SETTINGS
+            .into_iter()
+            .filter(|key| table.remove(*key).is_some())
+            .map(ToString::to_string)
+            .collect();
+        if let Some(toml::Value::Array(servers)) = table.get_mut("ice_servers") {
+            for (index, server) in servers.iter_mut().enumerate() {
+                if let Some(server) = server.as_table_mut() {
+                    if server.remove("credential").is_some() {
+                        redacted.push(format!("ice_servers[{index}].credential"));
+                    }
+                }
+            }
+        }
+
+        let mut output = String::new();
+        for key in redacted {
+            writeln!(output, "# {key} = <redacted, see --show-secrets>")?;
+        }
+        output.push_str(&toml::to_string_pretty(&table)?);
+        Ok(output)
+    }
+
     /// Get the listen address as a string
     pub fn listen_addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
@@ -53,9 +472,9 @@ impl Config {
     /// Get console commands as a vector
     pub fn get_console_commands(&self) -> Vec<String> {
         self.console_commands
-            .as_ref()
-            .map(|s| s.split(',').map(|cmd| cmd.trim().to_string()).collect())
-            .unwrap_or_default()
+            .iter()
+            .map(|cmd| cmd.trim().to_string())
+            .collect()
     }
 
     /// Check if using embedded assets (no `static_dir` override)
@@ -63,3 +482,109 @@ impl Config {
         self.static_dir.is_none()
     }
 }
+
+/// Whether an option from `source` takes precedence over the file
+fn overrides_file(source: Option<ValueSource>) -> bool {
+    matches!(
+        source,
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(args: &[&str], file: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        let matches = Config::command().get_matches_from(
+            ["webxash3d-proxy", "--server", "127.0.0.1:27015"]
+                .iter()
+                .chain(args),
+        );
+        let config = Config::from_arg_matches(&matches)?;
+        config.merge_file(toml::from_str(file)?, &matches)
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let config = merge(
+            &["--max-sessions", "8"],
+            "max_sessions = 2\nmax-sessions-per-ip = 3\nport = 28000",
+        )
+        .unwrap();
+
+        assert_eq!(config.max_sessions, 8);
+        assert_eq!(config.max_sessions_per_ip, 3);
+        assert_eq!(config.port, 28000);
+        assert_eq!(config.server.as_deref(), Some("127.0.0.1:27015"));
+    }
+
+    #[test]
+    fn environment_overrides_the_file_but_defaults_do_not() {
+        assert!(overrides_file(Some(ValueSource::CommandLine)));
+        assert!(overrides_file(Some(ValueSource::EnvVariable)));
+        assert!(!overrides_file(Some(ValueSource::DefaultValue)));
+        assert!(!overrides_file(None));
+    }
+
+    #[test]
+    fn file_only_settings_are_merged() {
+        let config = merge(
+            &[],
+            r#"
+            [files_map]
+            "valve.zip" = "/data/valve.zip"
+
+            [[servers]]
+            name = "hldm"
+            address = "10.0.0.6:27015"
+            game_dir = "valve"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.servers.len(), 1);
+        assert_eq!(config.servers[0].game_dir, "valve");
+        assert_eq!(config.files_map["valve.zip"], "/data/valve.zip");
+    }
+
+    #[test]
+    fn unknown_and_invocation_settings_are_rejected() {
+        assert!(merge(&[], "max_sesions = 2").is_err());
+        assert!(merge(&[], "show_secrets = true").is_err());
+        assert!(merge(&[], "port = \"http\"").is_err());
+    }
+
+    #[test]
+    fn printed_config_redacts_secrets() {
+        let mut config = merge(
+            &["--admin-token", "s3cret"],
+            r#"
+            auth_password = "hunter2"
+
+            [[ice_servers]]
+            urls = ["turn:turn.example.com"]
+            username = "player"
+            credential = "turnpass"
+            "#,
+        )
+        .unwrap();
+
+        let printed = config.to_toml().unwrap();
+        for secret in ["s3cret", "hunter2", "turnpass"] {
+            assert!(!printed.contains(secret), "{printed}");
+        }
+        assert!(printed.contains("# admin_token = <redacted"));
+        assert!(printed.contains("# ice_servers[0].credential = <redacted"));
+        assert!(printed.contains("username = \"player\""));
+
+        config.show_secrets = true;
+        let printed = config.to_toml().unwrap();
+        let reloaded: Config = toml::from_str(&printed).unwrap();
+        assert_eq!(reloaded.auth_password.as_deref(), Some("hunter2"));
+        assert_eq!(reloaded.admin_token.as_deref(), Some("s3cret"));
+        assert_eq!(
+            reloaded.ice_servers[0].credential.as_deref(),
+            Some("turnpass")
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Liveness and readiness checks for orchestrators and load balancers.
+//!
+//! Readiness probes every configured game server with `A2S_INFO` and checks
+//! that `--package-zip` can be read. The proxy is ready while at least one
+//! game server answers, so one dead backend does not take every other server
+//! out of the load balancer. Probe results are reused for
+//! `--server-info-cache-secs`, however often the orchestrator asks.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures::future::join_all;
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+
+use crate::a2s::{self, ServerInfo};
+use crate::servers::ServerEntry;
+use crate::state::AppState;
+
+/// Result of one readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// What was checked, e.g. `server:default` or `package_zip`
+    pub name: String,
+    /// Whether the check passed
+    pub ok: bool,
+    /// How long the check took
+    pub latency_ms: f64,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `A2S_INFO` answer of a game server check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<ServerInfo>,
+}
+
+/// Readiness report served on `/health/ready`
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// `ready`, `not_ready` or `draining`
+    pub status: &'static str,
+    /// Individual check results
+    pub checks: Vec<Check>,
+}
+
+impl Readiness {
+    /// Whether the proxy should receive new players
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+/// Last probe of one game server address
+type ProbeSlot = Arc<Mutex<Option<(Instant, Check)>>>;
+
+/// Recent `A2S_INFO` probes of the game servers
+pub struct Probes {
+    ttl: Duration,
+    entries: DashMap<String, ProbeSlot>,
+}
+
+impl Probes {
+    /// Create an empty cache keeping probe results for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Cached probe of a server, probing it again if the result is stale
+    ///
+    /// Concurrent callers for the same server wait for a single probe.
+    async fn check(&self, server: &ServerEntry, timeout: Duration) -> Check {
+        let slot = self
+            .entries
+            .entry(server.address.clone())
+            .or_default()
+            .clone();
+        let mut cached = slot.lock().await;
+
+        let mut check = match cached.as_ref() {
+            Some((probed_at, check)) if probed_at.elapsed() < self.ttl => check.clone(),
+            _ => {
+                let check = check_server(&server.address, timeout).await;
+                *cached = Some((Instant::now(), check.clone()));
+                check
+            }
+        };
+        check.name = format!("server:{}", server.name);
+        check
+    }
+}
+
+/// Run all readiness checks concurrently
+pub async fn readiness(state: &AppState) -> Readiness {
+    let settings = state.settings();
+    let timeout = Duration::from_millis(settings.config.health_probe_timeout_ms);
+
+    let server_checks = join_all(
+        settings
+            .servers
+            .iter()
+            .map(|server| state.probes.check(server, timeout)),
+    );
+    let (mut checks, package) = tokio::join!(server_checks, async {
+        match settings.config.package_zip.as_deref() {
+            Some(path) => Some(check_package(path).await),
+            None => None,
+        }
+    });
+
+    let ready = checks.iter().any(|c| c.ok) && package.as_ref().is_none_or(|c| c.ok);
+    checks.extend(package);
+
+    let status = if state.shutdown.is_draining() {
+        "draining"
+    } else if ready {
+        "ready"
+    } else {
+        "not_ready"
+    };
+
+    Readiness { status, checks }
+}
+
+/// Probe a game server with `A2S_INFO`; the caller names the check
+async fn check_server(address: &str, timeout: Duration) -> Check {
+    let started = Instant::now();
+    let result = a2s::info(address, timeout).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    match result {
+        Ok(info) => Check {
+            name: String::new(),
+            ok: true,
+            latency_ms,
+            error: None,
+            info: Some(info),
+        },
+        Err(e) => Check {
+            name: String::new(),
+            ok: false,
+            latency_ms,
+            error: Some(e.to_string()),
+            info: None,
+        },
+    }
+}
+
+/// Check that the game package exists and can be read
+async fn check_package(path: &str) -> Check {
+    let started = Instant::now();
+    let result = async {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic).await?;
+        Ok::<_, std::io::Error>(())
+    }
+    .await;
+
+    Check {
+        name: "package_zip".to_string(),
+        ok: result.is_ok(),
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        error: result.err().map(|e| format!("{path}: {e}")),
+        info: None,
+    }
+}
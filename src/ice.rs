@@ -0,0 +1,223 @@
+//! ICE server (STUN/TURN) configuration shared by the proxy and the browser.
+//!
+//! TURN credentials are either static or generated per session using the
+//! TURN REST API scheme: the username is `<expiry unix time>:<client id>` and
+//! the password is `base64(HMAC-SHA1(secret, username))`, which coturn and
+//! other TURN servers validate with the same shared secret.
+//!
+//! It also sets up the UDP sockets the proxy's own ICE agents gather on.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use tokio::net::UdpSocket;
+use tracing::info;
+use webrtc::ice::udp_mux::{UDPMuxDefault, UDPMuxParams};
+use webrtc::ice::udp_network::{EphemeralUDP, UDPNetwork};
+use webrtc::ice_transport::ice_credential_type::RTCIceCredentialType;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+
+use crate::config::Config;
+use crate::target_policy::parse_port_range;
+use crate::turn_server::TurnRelay;
+
+/// ICE server entry in the shape of the browser's `RTCIceServer`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IceServer {
+    /// STUN/TURN URLs
+    pub urls: Vec<String>,
+    /// TURN username
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// TURN password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+impl From<IceServer> for RTCIceServer {
+    fn from(server: IceServer) -> Self {
+        Self {
+            urls: server.urls,
+            username: server.username.unwrap_or_default(),
+            credential: server.credential.unwrap_or_default(),
+            credential_type: RTCIceCredentialType::Password,
+        }
+    }
+}
+
+/// How TURN credentials are obtained
+#[derive(Debug, Clone)]
+enum TurnAuth {
+    /// No credentials (only valid without TURN servers)
+    None,
+    /// Fixed username and password
+    Static {
+        username: String,
+        credential: String,
+    },
+    /// Time-limited credentials derived from a shared secret
+    SharedSecret { secret: String, ttl: Duration },
+}
+
+/// Configured STUN and TURN servers
+#[derive(Debug, Clone)]
+pub struct IceServers {
+    stun_urls: Vec<String>,
+    turn_urls: Vec<String>,
+    turn_auth: TurnAuth,
+    /// Servers from the configuration file, passed on as given
+    listed: Vec<IceServer>,
+    /// Embedded TURN server, if running
+    embedded_turn: Option<Arc<TurnRelay>>,
+    /// Lifetime of generated credentials
+    ttl: Duration,
+}
+
+impl IceServers {
+    /// Build the ICE server list from CLI options, the configuration file and
+    /// the embedded TURN server
+    pub fn from_config(
+        config: &Config,
+        embedded_turn: Option<Arc<TurnRelay>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let turn_auth = match (
+            &config.turn_secret,
+            &config.turn_username,
+            &config.turn_credential,
+        ) {
+            (Some(secret), _, _) => TurnAuth::SharedSecret {
+                secret: secret.clone(),
+                ttl: Duration::from_secs(config.turn_ttl),
+            },
+            (None, Some(username), Some(credential)) => TurnAuth::Static {
+                username: username.clone(),
+                credential: credential.clone(),
+            },
+            _ => TurnAuth::None,
+        };
+
+        let turn_urls = non_empty(&config.turn_server);
+        if !turn_urls.is_empty() && matches!(turn_auth, TurnAuth::None) {
+            return Err("--turn-server requires --turn-secret or \
+                        --turn-username with --turn-credential"
+                .into());
+        }
+
+        Ok(Self {
+            stun_urls: non_empty(&config.stun_server),
+            turn_urls,
+            turn_auth,
+            listed: config.ice_servers.clone(),
+            embedded_turn,
+            ttl: Duration::from_secs(config.turn_ttl),
+        })
+    }
+
+    /// ICE servers for one session, with fresh TURN credentials if applicable
+    ///
+    /// `session_id` ends up in the TURN username so relay allocations can be
+    /// traced back to a session in TURN server logs.
+    pub fn for_session(&self, session_id: &str) -> Vec<IceServer> {
+        let mut servers = Vec::new();
+
+        if !self.stun_urls.is_empty() {
+            servers.push(IceServer {
+                urls: self.stun_urls.clone(),
+                username: None,
+                credential: None,
+            });
+        }
+
+        if !self.turn_urls.is_empty() {
+            let (username, credential) = match &self.turn_auth {
+                TurnAuth::None => (None, None),
+                TurnAuth::Static {
+                    username,
+                    credential,
+                } => (Some(username.clone()), Some(credential.clone())),
+                TurnAuth::SharedSecret { secret, ttl } => {
+                    let (username, credential) = rest_api_credentials(secret, *ttl, session_id);
+                    (Some(username), Some(credential))
+                }
+            };
+
+            servers.push(IceServer {
+                urls: self.turn_urls.clone(),
+                username,
+                credential,
+            });
+        }
+
+        servers.extend(self.listed.iter().cloned());
+
+        if let Some(relay) = &self.embedded_turn {
+            let (username, credential) = rest_api_credentials(relay.secret(), self.ttl, session_id);
+            servers.push(IceServer {
+                urls: relay.urls().to_vec(),
+                username: Some(username),
+                credential: Some(credential),
+            });
+        }
+
+        servers
+    }
+}
+
+/// Generate TURN REST API credentials valid for `ttl`
+pub fn rest_api_credentials(secret: &str, ttl: Duration, session_id: &str) -> (String, String) {
+    let expiry = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .saturating_add(ttl)
+        .as_secs();
+    let username = format!("{expiry}:{session_id}");
+    let credential = rest_api_password(secret, &username);
+
+    (username, credential)
+}
+
+/// Password for a TURN REST API username: `base64(HMAC-SHA1(secret, username))`
+pub fn rest_api_password(secret: &str, username: &str) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// UDP sockets used by the proxy's ICE agents
+///
+/// With `--ice-udp-port` every peer connection shares one socket through an
+/// ICE UDP mux; with `--ice-port-range` each connection still gets its own
+/// socket but only from the given range.
+pub async fn udp_network(config: &Config) -> Result<UDPNetwork, Box<dyn std::error::Error>> {
+    if let Some(port) = config.ice_udp_port {
+        let socket = UdpSocket::bind((config.host.as_str(), port)).await?;
+        info!("ICE UDP mux listening on {}", socket.local_addr()?);
+        return Ok(UDPNetwork::Muxed(UDPMuxDefault::new(UDPMuxParams::new(
+            socket,
+        ))));
+    }
+
+    let Some(ref range) = config.ice_port_range else {
+        return Ok(UDPNetwork::Ephemeral(EphemeralUDP::default()));
+    };
+
+    let ports = parse_port_range(range).map_err(|_| format!("Invalid ICE port range: {range}"))?;
+    let ephemeral = EphemeralUDP::new(*ports.start(), *ports.end())
+        .map_err(|e| format!("Invalid ICE port range {range}: {e}"))?;
+
+    Ok(UDPNetwork::Ephemeral(ephemeral))
+}
+
+/// Drop empty entries so `--stun-server ""` disables STUN
+fn non_empty(urls: &[String]) -> Vec<String> {
+    urls.iter()
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
@@ -0,0 +1,297 @@
+//! Concurrent session limits, globally and per client IP.
+//!
+//! Every session holds a WebRTC peer connection and a UDP socket, so the
+//! number of sessions is capped before any of them is allocated. Behind a
+//! reverse proxy the client IP is taken from `--client-ip-header`, but only
+//! when the connection comes from one of the `--trusted-proxy` networks.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+
+use axum::http::{HeaderMap, HeaderName};
+use ipnet::IpNet;
+
+use crate::config::Config;
+use crate::target_policy::parse_cidr;
+
+/// Why a new session was refused
+#[derive(Debug, Clone, Copy)]
+pub enum LimitExceeded {
+    /// `--max-sessions` sessions are already running
+    Global(usize),
+    /// The client IP already runs `--max-sessions-per-ip` sessions
+    PerIp(usize),
+}
+
+impl LimitExceeded {
+    /// Metric label of the limit
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Global(_) => "global",
+            Self::PerIp(_) => "per_ip",
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global(max) => write!(f, "Proxy is full ({max} sessions), try again later"),
+            Self::PerIp(max) => write!(f, "Too many sessions from your address (limit {max})"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Limits and client IP rules from the configuration
+struct Rules {
+    /// Maximum concurrent sessions, 0 for unlimited
+    max_sessions: usize,
+    /// Maximum concurrent sessions per client IP, 0 for unlimited
+    max_per_ip: usize,
+    /// Peers whose client IP header is believed
+    trusted_proxies: Vec<IpNet>,
+    /// Header carrying the client IP, e.g. `X-Forwarded-For`
+    ip_header: HeaderName,
+}
+
+impl Rules {
+    fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let trusted_proxies = config
+            .trusted_proxy
+            .iter()
+            .map(|s| parse_cidr(s).map_err(|_| format!("Invalid trusted proxy '{s}'")))
+            .collect::<Result<_, _>>()?;
+        let ip_header = HeaderName::try_from(config.client_ip_header.as_str())
+            .map_err(|_| format!("Invalid client IP header '{}'", config.client_ip_header))?;
+
+        Ok(Self {
+            max_sessions: config.max_sessions,
+            max_per_ip: config.max_sessions_per_ip,
+            trusted_proxies,
+            ip_header,
+        })
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Session counters and the limits they are checked against
+pub struct SessionLimits {
+    rules: RwLock<Rules>,
+    counts: Mutex<Counts>,
+}
+
+impl SessionLimits {
+    /// Build the limits from CLI options
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            rules: RwLock::new(Rules::from_config(config)?),
+            counts: Mutex::new(Counts::default()),
+        })
+    }
+
+    /// Apply new limits to future sessions
+    ///
+    /// Running sessions keep their slots, even when they are now over a limit.
+    pub fn reconfigure(&self, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        let rules = Rules::from_config(config)?;
+        *self.rules.write().expect("limits lock poisoned") = rules;
+        Ok(())
+    }
+
+    /// IP of the client behind a connection
+    ///
+    /// For `X-Forwarded-For` style lists the rightmost address that is not
+    /// itself a trusted proxy wins, since anything left of it may be forged.
+    pub fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        let rules = self.rules.read().expect("limits lock poisoned");
+        let peer = peer.ip().to_canonical();
+        if !rules.is_trusted(peer) {
+            return peer;
+        }
+
+        headers
+            .get_all(&rules.ip_header)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|entry| entry.trim().parse::<IpAddr>().ok())
+            .map(|ip| ip.to_canonical())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .find(|ip| !rules.is_trusted(*ip))
+            .unwrap_or(peer)
+    }
+
+    /// Reserve a session slot for a client IP
+    ///
+    /// The slot is released when the returned permit is dropped.
+    pub fn acquire(self: &Arc<Self>, ip: IpAddr) -> Result<SessionPermit, LimitExceeded> {
+        let (max_sessions, max_per_ip) = {
+            let rules = self.rules.read().expect("limits lock poisoned");
+            (rules.max_sessions, rules.max_per_ip)
+        };
+        let mut counts = self.counts.lock().expect("limits lock poisoned");
+        if max_sessions > 0 && counts.total >= max_sessions {
+            return Err(LimitExceeded::Global(max_sessions));
+        }
+        let per_ip = counts.per_ip.entry(ip).or_default();
+        if max_per_ip > 0 && *per_ip >= max_per_ip {
+            return Err(LimitExceeded::PerIp(max_per_ip));
+        }
+        *per_ip += 1;
+        counts.total += 1;
+
+        Ok(SessionPermit {
+            limits: self.clone(),
+            ip,
+        })
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().expect("limits lock poisoned");
+        counts.total -= 1;
+        if let Some(n) = counts.per_ip.get_mut(&ip) {
+            *n -= 1;
+            if *n == 0 {
+                counts.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// One reserved session slot
+pub struct SessionPermit {
+    limits: Arc<SessionLimits>,
+    ip: IpAddr,
+}
+
+impl SessionPermit {
+    /// Client IP the slot counts against
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+}
+
+impl Drop for SessionPermit {
+    fn drop(&mut self) {
+        self.limits.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use clap::Parser;
+
+    use super::*;
+
+    fn limits(args: &[&str]) -> Arc<SessionLimits> {
+        let config = Config::parse_from(
+            ["webxash3d-proxy", "--server", "127.0.0.1:27015"]
+                .iter()
+                .chain(args),
+        );
+        Arc::new(SessionLimits::from_config(&config).unwrap())
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_forge_their_address() {
+        let limits = limits(&["--trusted-proxy", "10.0.0.0/8"]);
+        let peer = "203.0.113.7:50000".parse().unwrap();
+        assert_eq!(
+            limits.client_ip(peer, &forwarded(&["198.51.100.1"])),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_pass_the_rightmost_untrusted_address() {
+        let limits = limits(&["--trusted-proxy", "10.0.0.0/8, ::1"]);
+        let peer = "10.0.0.2:50000".parse().unwrap();
+
+        // The leftmost entry is whatever the client sent and may be forged
+        assert_eq!(
+            limits.client_ip(peer, &forwarded(&["1.1.1.1, 198.51.100.1, 10.0.0.3"])),
+            ip("198.51.100.1")
+        );
+        // Repeated headers are one list, in order
+        assert_eq!(
+            limits.client_ip(peer, &forwarded(&["1.1.1.1", "198.51.100.1"])),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            limits.client_ip(peer, &forwarded(&["::ffff:198.51.100.1"])),
+            ip("198.51.100.1")
+        );
+        // Garbage and proxy-only chains fall back to the peer
+        assert_eq!(
+            limits.client_ip(peer, &forwarded(&["unknown, 10.0.0.3"])),
+            ip("10.0.0.2")
+        );
+        assert_eq!(limits.client_ip(peer, &HeaderMap::new()), ip("10.0.0.2"));
+        assert_eq!(
+            limits.client_ip(
+                "[::ffff:10.0.0.2]:50000".parse().unwrap(),
+                &forwarded(&["198.51.100.1"])
+            ),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn reads_the_configured_header() {
+        let limits = limits(&[
+            "--trusted-proxy",
+            "127.0.0.1",
+            "--client-ip-header",
+            "x-real-ip",
+        ]);
+        let mut headers = forwarded(&["198.51.100.1"]);
+        headers.insert("x-real-ip", HeaderValue::from_static("198.51.100.2"));
+        assert_eq!(
+            limits.client_ip("127.0.0.1:50000".parse().unwrap(), &headers),
+            ip("198.51.100.2")
+        );
+    }
+
+    #[test]
+    fn enforces_session_limits_until_permits_drop() {
+        let limits = limits(&["--max-sessions", "2", "--max-sessions-per-ip", "1"]);
+        let first = limits.acquire(ip("198.51.100.1")).unwrap();
+        assert!(matches!(
+            limits.acquire(ip("198.51.100.1")),
+            Err(LimitExceeded::PerIp(1))
+        ));
+        let _second = limits.acquire(ip("198.51.100.2")).unwrap();
+        assert!(matches!(
+            limits.acquire(ip("198.51.100.3")),
+            Err(LimitExceeded::Global(2))
+        ));
+        drop(first);
+        assert!(limits.acquire(ip("198.51.100.1")).is_ok());
+    }
+}
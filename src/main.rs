@@ -3,42 +3,77 @@
 //! This proxy enables browser clients to connect to traditional game servers
 //! by bridging WebRTC data channels to UDP sockets.
 
+mod a2s;
+mod admin;
 mod assets;
+mod auth;
 mod bridge;
 mod config;
+mod health;
+mod ice;
+mod limits;
+mod metrics;
+mod origin;
+mod protocol;
+mod rate_limit;
+mod reload;
+mod server_browser;
+mod server_info;
+mod servers;
+mod sessions;
+mod shutdown;
 mod signaling;
+mod state;
+mod target_policy;
+mod tls;
+mod transport;
+mod turn_server;
+mod webtransport;
 
-use std::sync::Arc;
+use std::net::SocketAddr;
 
 use axum::body::Body;
-use axum::extract::State;
-use axum::http::{header, Request, Response, StatusCode};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, Request, Response, StatusCode};
 use axum::{
     extract::ws::{WebSocket, WebSocketUpgrade},
+    middleware,
     response::IntoResponse,
     routing::get,
     Json, Router,
 };
-use clap::Parser;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
-use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
-use config::Config;
+use config::{CommandLine, Config};
+use limits::SessionPermit;
+use protocol::{ErrorCode, ServerMessage};
+use servers::ServerEntry;
+use state::{AppState, Settings};
 
-/// Application state shared across handlers
-#[derive(Clone)]
-struct AppState {
-    config: Arc<Config>,
+/// Query parameters selecting a game server by name
+#[derive(Deserialize)]
+struct ServerQuery {
+    server: Option<String>,
+}
+
+/// WebSocket query parameters selecting a named server or a custom `host:port` target
+#[derive(Deserialize)]
+struct WsQuery {
+    server: Option<String>,
+    target: Option<String>,
+    /// Password or token, may instead be sent in the client `hello`
+    auth: Option<String>,
 }
 
 /// Client configuration response
 #[derive(Serialize)]
 struct ClientConfig {
+    server: String,
     arguments: Vec<String>,
     console: Vec<String>,
     game_dir: String,
@@ -47,6 +82,9 @@ struct ClientConfig {
     files_map: std::collections::HashMap<String, String>,
     proxy_host: String,
     proxy_port: u16,
+    webtransport_port: Option<u16>,
+    /// Whether players must enter a password or bring a token
+    auth_required: bool,
 }
 
 #[derive(Serialize)]
@@ -61,7 +99,15 @@ struct ClientLibraries {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse CLI arguments
-    let config = Config::parse();
+    let command_line = CommandLine::parse()?;
+    let config = command_line.load()?;
+    if config.print_effective {
+        #[allow(clippy::print_stdout)] // the point of --print-config
+        {
+            print!("{}", config.to_toml()?);
+        }
+        return Ok(());
+    }
 
     // Setup logging
     let filter = if config.verbose {
@@ -75,10 +121,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_target(false)
         .init();
 
+    let state = AppState::from_config(config.clone()).await?;
+
     info!("Starting webxash3d-proxy");
-    info!("Game server: {}", config.server);
+    if let Some(ref path) = config.settings_file {
+        info!("Configuration file: {}", path);
+    }
+    for server in state.settings().servers.iter() {
+        info!(
+            "Game server: {} -> {} ({})",
+            server.name, server.address, server.game_dir
+        );
+    }
     info!("Listen address: {}", config.listen_addr());
-    info!("Game directory: {}", config.game_dir);
+
+    if config.allow_custom_targets {
+        info!("Custom targets enabled, restricted by target policy");
+    }
 
     if let Some(ref ip) = config.public_ip {
         info!("Public IP for ICE: {}", ip);
@@ -96,22 +155,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Development mode: serving static files from {}", static_dir);
     }
 
-    let state = AppState {
-        config: Arc::new(config.clone()),
-    };
-
     // Build router with API routes
-    let app = Router::new()
+    if let Some(endpoint) = webtransport::bind(&config)? {
+        tokio::spawn(webtransport::serve(endpoint, state.clone()));
+    }
+    let drain_state = state.clone();
+    tokio::spawn(state.server_browser.clone().run(state.clone()));
+    tokio::spawn(reload::watch(state.clone(), command_line));
+
+    if state.settings().origins.is_permissive() {
+        warn!("Any website may use this proxy, restrict it with --allowed-origin");
+    }
+
+    // Game endpoints only serve pages from allowed origins
+    let game_routes = Router::new()
         .route("/ws", get(ws_handler))
         .route("/websocket", get(ws_handler))
-        .route("/health", get(health_handler))
         .route("/config", get(config_handler))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        )
+        .route("/api/server/info", get(server_info_handler))
+        .route("/api/servers", get(servers_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            origin::enforce,
+        ));
+
+    let app = Router::new()
+        .merge(game_routes)
+        .route("/health", get(health_handler))
+        .route("/health/live", get(live_handler))
+        .route("/health/ready", get(ready_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/play/:name", get(play_handler));
+    if config.admin_token.is_some() {
+        info!("Admin API enabled at /admin");
+    }
+    let app = app
+        .nest("/admin", admin::router(&state))
+        .layer(origin::cors_layer(state.clone()))
         .with_state(state.clone());
 
     // Add static file serving
@@ -128,10 +208,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // Start server
-    let listener = tokio::net::TcpListener::bind(config.listen_addr()).await?;
-    info!("Server listening on http://{}", config.listen_addr());
+    serve(&config, app, drain_state).await
+}
 
-    axum::serve(listener, app).await?;
+/// Serve the app over HTTPS when TLS is configured, plain HTTP otherwise,
+/// until a termination signal drained all sessions
+async fn serve(
+    config: &Config,
+    app: Router,
+    drain_state: AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    if let Some(tls) = tls::server_config(config)? {
+        let handle = axum_server::Handle::new();
+        let shutdown_handle = handle.clone();
+        tokio::spawn(async move {
+            shutdown::drain_on_signal(drain_state).await;
+            shutdown_handle.graceful_shutdown(None);
+        });
+
+        let listener = std::net::TcpListener::bind(config.listen_addr())?;
+        listener.set_nonblocking(true)?;
+        info!("Server listening on https://{}", config.listen_addr());
+
+        axum_server::from_tcp_rustls(listener, tls)
+            .handle(handle)
+            .serve(app)
+            .await?;
+    } else {
+        let listener = tokio::net::TcpListener::bind(config.listen_addr()).await?;
+        info!("Server listening on http://{}", config.listen_addr());
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown::drain_on_signal(drain_state))
+            .await?;
+    }
 
     Ok(())
 }
@@ -152,7 +263,8 @@ async fn serve_static(path: String, state: AppState) -> Response<Body> {
 
 /// Serve valve.zip from the `package_zip` path
 async fn serve_package_zip(state: &AppState) -> Response<Body> {
-    let Some(ref package_path) = state.config.package_zip else {
+    let settings = state.settings();
+    let Some(ref package_path) = settings.config.package_zip else {
         return Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Body::from("valve.zip not configured (use --package-zip)"))
@@ -203,26 +315,201 @@ async fn serve_package_zip(state: &AppState) -> Response<Body> {
         .expect("building response should not fail")
 }
 
+/// Return a 404 response for an unknown server name
+fn unknown_server(name: Option<&str>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!(
+            "Unknown server: {}",
+            name.unwrap_or_default()
+        )))
+        .expect("building response should not fail")
+}
+
+/// Serve the web client for a specific server (`/play/<name>`)
+///
+/// The client reads the server name back from its own URL.
+async fn play_handler(Path(name): Path<String>, State(state): State<AppState>) -> Response<Body> {
+    let settings = state.settings();
+    if settings.servers.get(Some(&name)).is_none() {
+        return unknown_server(Some(&name));
+    }
+
+    let Some(ref static_dir) = settings.config.static_dir else {
+        return assets::serve_embedded("index.html");
+    };
+
+    match tokio::fs::read(std::path::Path::new(static_dir).join("index.html")).await {
+        Ok(contents) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/html")
+            .body(Body::from(contents))
+            .expect("building response should not fail"),
+        Err(e) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(format!("Failed to read index.html: {e}")))
+            .expect("building response should not fail"),
+    }
+}
+
 /// WebSocket upgrade handler
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<WsQuery>,
+    State(state): State<AppState>,
+) -> Response<Body> {
+    let server = match state.session_server(query.server.as_deref(), query.target) {
+        Ok(server) => server,
+        Err((status, message)) => {
+            return Response::builder()
+                .status(status)
+                .body(Body::from(message))
+                .expect("building response should not fail");
+        }
+    };
+
+    // Refused after the upgrade so the browser gets a signaling error it can show
+    let browser_ip = state.limits.client_ip(remote_addr, &headers);
+    let permit = match state.limits.acquire(browser_ip) {
+        Ok(permit) => permit,
+        Err(limit) => {
+            warn!(remote = %remote_addr, client_ip = %browser_ip, %limit, "Session limit reached");
+            state.metrics.session_rejected(limit.as_str());
+            let msg = ServerMessage::error(ErrorCode::TooManySessions, limit.to_string());
+            return ws
+                .on_upgrade(move |socket| async move {
+                    signaling::reject_websocket(socket, &msg).await;
+                })
+                .into_response();
+        }
+    };
+
     let client_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
-    ws.on_upgrade(move |socket| handle_socket(socket, state, client_id))
+    ws.on_upgrade(move |socket| {
+        handle_socket(
+            socket,
+            state,
+            server,
+            client_id,
+            remote_addr,
+            permit,
+            query.auth,
+        )
+    })
+    .into_response()
 }
 
 /// Handle upgraded WebSocket connection
-async fn handle_socket(socket: WebSocket, state: AppState, client_id: String) {
-    signaling::handle_websocket(socket, state.config, client_id).await;
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    server: ServerEntry,
+    client_id: String,
+    remote_addr: SocketAddr,
+    permit: SessionPermit,
+    auth: Option<String>,
+) {
+    signaling::handle_websocket(
+        socket,
+        state,
+        server,
+        client_id,
+        remote_addr,
+        permit.ip(),
+        auth,
+    )
+    .await;
+    // The session slot is free again once the socket closed
+    drop(permit);
+}
+
+/// Health check endpoint, `503 DRAINING` once shutdown started
+async fn health_handler(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    if state.shutdown.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "DRAINING")
+    } else {
+        (StatusCode::OK, "OK")
+    }
+}
+
+/// Liveness endpoint: the process is up and serving HTTP
+async fn live_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "alive" }))
+}
+
+/// Readiness endpoint: game servers answer and the game package is readable
+async fn ready_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let readiness = health::readiness(&state).await;
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
 }
 
-/// Health check endpoint
-async fn health_handler() -> &'static str {
-    "OK"
+/// Prometheus metrics endpoint
+async fn metrics_handler(State(state): State<AppState>) -> Response<Body> {
+    match state.metrics.render() {
+        Ok(body) => Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(body))
+            .expect("building response should not fail"),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .expect("building response should not fail"),
+    }
 }
 
 /// Client configuration endpoint
-/// Returns configuration needed by the `Xash3D` WASM client
-async fn config_handler(State(state): State<AppState>) -> Json<ClientConfig> {
-    let game_dir = &state.config.game_dir;
+/// Returns configuration needed by the `Xash3D` WASM client for the selected server
+async fn config_handler(
+    Query(query): Query<ServerQuery>,
+    State(state): State<AppState>,
+) -> Response<Body> {
+    let settings = state.settings();
+    let Some(server) = settings.servers.get(query.server.as_deref()) else {
+        return unknown_server(query.server.as_deref());
+    };
+
+    Json(client_config(&settings, server)).into_response()
+}
+
+/// Map, players and rules of a game server from cached A2S queries
+async fn server_info_handler(
+    Query(query): Query<ServerQuery>,
+    State(state): State<AppState>,
+) -> Response<Body> {
+    let settings = state.settings();
+    let Some(server) = settings.servers.get(query.server.as_deref()) else {
+        return unknown_server(query.server.as_deref());
+    };
+
+    match state.server_info.get(server).await {
+        Ok(details) => Json(&*details).into_response(),
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            Json(serde_json::json!({ "server": server.name, "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Server browser list, filtered and sorted by query parameters
+async fn servers_handler(
+    Query(query): Query<server_browser::BrowserQuery>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(state.server_browser.list(&query))
+}
+
+/// Build the `Xash3D` client configuration for a server
+fn client_config(settings: &Settings, server: &ServerEntry) -> ClientConfig {
+    let config = &settings.config;
+    let game_dir = &server.game_dir;
 
     // Build files_map to translate .so requests to .wasm files
     let mut files_map = std::collections::HashMap::new();
@@ -241,20 +528,24 @@ async fn config_handler(State(state): State<AppState>) -> Json<ClientConfig> {
         "/filesystem_stdio.wasm".to_string(),
     );
 
+    // Configured entries override the defaults, per-server ones override both
+    files_map.extend(config.files_map.clone());
+    files_map.extend(server.files_map.clone());
+
     // Use public_ip if provided, otherwise use host
-    let proxy_host = state
-        .config
+    let proxy_host = config
         .public_ip
         .clone()
-        .unwrap_or_else(|| state.config.host.clone());
+        .unwrap_or_else(|| config.host.clone());
 
-    Json(ClientConfig {
+    ClientConfig {
+        server: server.name.clone(),
         arguments: vec![
             "-windowed".to_string(),
             "-game".to_string(),
             game_dir.clone(),
         ],
-        console: state.config.get_console_commands(),
+        console: server.console_commands.clone(),
         game_dir: game_dir.clone(),
         libraries: ClientLibraries {
             // These paths are relative to the static directory
@@ -271,6 +562,8 @@ async fn config_handler(State(state): State<AppState>) -> Json<ClientConfig> {
         ],
         files_map,
         proxy_host,
-        proxy_port: state.config.port,
-    })
+        proxy_port: config.port,
+        webtransport_port: config.webtransport_port,
+        auth_required: settings.auth.is_enabled(),
+    }
 }
@@ -0,0 +1,158 @@
+//! Prometheus metrics exported on `/metrics`.
+
+use prometheus::{
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+
+use crate::protocol::TransportKind;
+
+/// Packet direction label values
+const TO_SERVER: &str = "client_to_server";
+const TO_CLIENT: &str = "server_to_client";
+
+/// Counters for one packet direction, resolved once so the hot path skips label lookups
+#[derive(Clone)]
+pub struct DirectionMetrics {
+    /// Packets forwarded
+    pub packets: IntCounter,
+    /// Payload bytes forwarded
+    pub bytes: IntCounter,
+    /// Packets that could not be sent
+    pub send_errors: IntCounter,
+    /// Packets dropped by a session rate limit
+    pub dropped: IntCounter,
+}
+
+impl DirectionMetrics {
+    /// Count one forwarded packet
+    pub fn record(&self, bytes: usize) {
+        self.packets.inc();
+        self.bytes.inc_by(bytes as u64);
+    }
+}
+
+/// All proxy metrics and the registry they are exported from
+pub struct Metrics {
+    registry: Registry,
+    /// Open signaling sockets
+    pub ws_sessions: IntGauge,
+    bridges: IntGaugeVec,
+    peer_states: IntCounterVec,
+    /// Peer connections that ended in ICE failure
+    pub ice_failures: IntCounter,
+    sessions_rejected: IntCounterVec,
+    /// Browser → game server traffic
+    pub to_server: DirectionMetrics,
+    /// Game server → browser traffic
+    pub to_client: DirectionMetrics,
+}
+
+impl Metrics {
+    /// Create and register all metrics
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("webxash3d".to_string()), None)?;
+
+        let ws_sessions = IntGauge::new("ws_sessions_active", "Open signaling WebSockets")?;
+        let bridges = IntGaugeVec::new(
+            Opts::new("bridges_active", "Running UDP bridges by transport"),
+            &["transport"],
+        )?;
+        let peer_states = IntCounterVec::new(
+            Opts::new(
+                "peer_state_transitions_total",
+                "Peer connection state changes by new state",
+            ),
+            &["state"],
+        )?;
+        let ice_failures = IntCounter::new(
+            "ice_failures_total",
+            "Peer connections that failed to connect",
+        )?;
+        let sessions_rejected = IntCounterVec::new(
+            Opts::new(
+                "sessions_rejected_total",
+                "Sessions refused by a session limit",
+            ),
+            &["limit"],
+        )?;
+        let packets = IntCounterVec::new(
+            Opts::new("packets_total", "Forwarded game packets by direction"),
+            &["direction"],
+        )?;
+        let bytes = IntCounterVec::new(
+            Opts::new("bytes_total", "Forwarded game payload bytes by direction"),
+            &["direction"],
+        )?;
+        let send_errors = IntCounterVec::new(
+            Opts::new(
+                "send_errors_total",
+                "Packets that failed to send by direction",
+            ),
+            &["direction"],
+        )?;
+
+        let dropped = IntCounterVec::new(
+            Opts::new(
+                "dropped_packets_total",
+                "Packets dropped by session rate limits by direction",
+            ),
+            &["direction"],
+        )?;
+
+        registry.register(Box::new(ws_sessions.clone()))?;
+        registry.register(Box::new(bridges.clone()))?;
+        registry.register(Box::new(peer_states.clone()))?;
+        registry.register(Box::new(ice_failures.clone()))?;
+        registry.register(Box::new(sessions_rejected.clone()))?;
+        registry.register(Box::new(packets.clone()))?;
+        registry.register(Box::new(bytes.clone()))?;
+        registry.register(Box::new(send_errors.clone()))?;
+        registry.register(Box::new(dropped.clone()))?;
+
+        let direction = |label: &str| DirectionMetrics {
+            packets: packets.with_label_values(&[label]),
+            bytes: bytes.with_label_values(&[label]),
+            send_errors: send_errors.with_label_values(&[label]),
+            dropped: dropped.with_label_values(&[label]),
+        };
+
+        Ok(Self {
+            to_server: direction(TO_SERVER),
+            to_client: direction(TO_CLIENT),
+            registry,
+            ws_sessions,
+            bridges,
+            peer_states,
+            ice_failures,
+            sessions_rejected,
+        })
+    }
+
+    /// Gauge of running bridges for a transport
+    pub fn bridges(&self, transport: TransportKind) -> IntGauge {
+        self.bridges.with_label_values(&[transport.as_str()])
+    }
+
+    /// Count a peer connection state change
+    pub fn peer_state(&self, state: RTCPeerConnectionState) {
+        self.peer_states
+            .with_label_values(&[&state.to_string()])
+            .inc();
+        if state == RTCPeerConnectionState::Failed {
+            self.ice_failures.inc();
+        }
+    }
+
+    /// Count a session refused by a limit
+    pub fn session_rejected(&self, limit: &str) {
+        self.sessions_rejected.with_label_values(&[limit]).inc();
+    }
+
+    /// Render all metrics in the Prometheus text format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
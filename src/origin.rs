@@ -0,0 +1,273 @@
+//! Origin allowlist for the game endpoints.
+//!
+//! CORS only stops other websites from reading responses; it does not stop
+//! them from opening the signaling WebSocket or WebTransport session. So the
+//! allowlist is applied twice: to the CORS layer, and as a check of the
+//! `Origin` (or, without one, `Referer`) header on `/ws`, `/config` and the
+//! `/api` routes. Pages served from the proxy's own hostname are always allowed.
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use axum::middleware::Next;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::state::AppState;
+
+/// Websites allowed to use the proxy
+#[derive(Clone)]
+pub struct OriginPolicy {
+    /// `scheme://host[:port]` origins, lowercase; a host may start with `*.`
+    allowed: Vec<String>,
+    /// Any website may use the proxy (development mode)
+    any: bool,
+}
+
+impl OriginPolicy {
+    /// Parse `--allowed-origin`
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut allowed = Vec::new();
+        let mut any = false;
+        for origin in &config.allowed_origin {
+            let origin = origin.trim().trim_end_matches('/').to_lowercase();
+            if origin == "*" {
+                any = true;
+                continue;
+            }
+            match origin.split_once("://") {
+                Some((scheme, host))
+                    if !scheme.is_empty() && !host.is_empty() && !host.contains('/') =>
+                {
+                    if host.contains('*') && !is_valid_wildcard(host) {
+                        return Err(format!(
+                            "Invalid allowed origin '{origin}' (a wildcard must be a leading \
+                             '*.' followed by a domain, e.g. https://*.example.com)"
+                        )
+                        .into());
+                    }
+                    allowed.push(origin);
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid allowed origin '{origin}' (expected scheme://host[:port])"
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(Self { allowed, any })
+    }
+
+    /// Whether every website is allowed
+    pub fn is_permissive(&self) -> bool {
+        self.any
+    }
+
+    /// Whether an origin is on the allowlist
+    pub fn allows(&self, origin: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let origin = origin.to_lowercase();
+        let Some((scheme, host)) = origin.split_once("://") else {
+            return false;
+        };
+        self.allowed.iter().any(|pattern| {
+            let (pattern_scheme, pattern_host) =
+                pattern.split_once("://").expect("validated in from_config");
+            pattern_scheme == scheme
+                && match pattern_host.strip_prefix("*.") {
+                    // Only subdomains: `*.example.com` must not match `evilexample.com`
+                    Some(domain) => host
+                        .strip_suffix(domain)
+                        .and_then(|sub| sub.strip_suffix('.'))
+                        .is_some_and(|sub| !sub.is_empty()),
+                    None => pattern_host == host,
+                }
+        })
+    }
+
+    /// Check the requesting page of a request for `host`
+    ///
+    /// Requests without `Origin` and `Referer` come from non-browser clients
+    /// and are let through; browsers always send `Origin` on WebSocket and
+    /// WebTransport connects.
+    pub fn check(&self, headers: &HeaderMap, host: Option<&str>) -> Result<(), String> {
+        if self.any {
+            return Ok(());
+        }
+        let origin = match headers.get(header::ORIGIN) {
+            Some(origin) => origin.to_str().unwrap_or_default().to_string(),
+            None => match headers.get(header::REFERER).and_then(|r| r.to_str().ok()) {
+                Some(referer) => referer_origin(referer),
+                None => return Ok(()),
+            },
+        };
+
+        if self.allows_from(&origin, host) {
+            Ok(())
+        } else {
+            Err(origin)
+        }
+    }
+
+    /// Whether a page at `origin` may use the proxy reached as `host`
+    fn allows_from(&self, origin: &str, host: Option<&str>) -> bool {
+        let same_host = host
+            .map(strip_port)
+            .is_some_and(|host| origin_host(origin).is_some_and(|o| o.eq_ignore_ascii_case(host)));
+        same_host || self.allows(origin)
+    }
+}
+
+/// CORS layer that only admits origins allowed by the current settings
+pub fn cors_layer(state: AppState) -> CorsLayer {
+    CorsLayer::new()
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .allow_origin(AllowOrigin::predicate(
+            move |origin: &HeaderValue, parts| {
+                let Ok(origin) = origin.to_str() else {
+                    return false;
+                };
+                let host = parts
+                    .headers
+                    .get(header::HOST)
+                    .and_then(|h| h.to_str().ok());
+                state.settings().origins.allows_from(origin, host)
+            },
+        ))
+}
+
+/// Middleware refusing requests from pages not on the allowlist
+pub async fn enforce(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok());
+    if let Err(origin) = state.settings().origins.check(request.headers(), host) {
+        warn!(
+            origin,
+            path = request.uri().path(),
+            "Request from foreign origin refused"
+        );
+        return Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("Origin not allowed"))
+            .expect("building response should not fail");
+    }
+    next.run(request).await
+}
+
+/// Whether a wildcard host is `*.` followed by a domain without wildcards
+fn is_valid_wildcard(host: &str) -> bool {
+    host.strip_prefix("*.").is_some_and(|domain| {
+        let domain = strip_port(domain);
+        !domain.is_empty()
+            && !domain.contains('*')
+            && domain.split('.').all(|label| !label.is_empty())
+    })
+}
+
+/// `scheme://host[:port]` part of a `Referer` URL
+fn referer_origin(referer: &str) -> String {
+    let Some((scheme, rest)) = referer.split_once("://") else {
+        return String::new();
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    format!("{scheme}://{}", &rest[..end])
+}
+
+/// Hostname of an origin, without scheme and port
+fn origin_host(origin: &str) -> Option<&str> {
+    origin.split_once("://").map(|(_, host)| strip_port(host))
+}
+
+/// Hostname without a trailing `:port`, keeping bracketed IPv6 addresses intact
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn policy(origins: &str) -> Result<OriginPolicy, Box<dyn std::error::Error>> {
+        let config = Config::parse_from([
+            "webxash3d-proxy",
+            "--server",
+            "127.0.0.1:27015",
+            "--allowed-origin",
+            origins,
+        ]);
+        OriginPolicy::from_config(&config)
+    }
+
+    #[test]
+    fn allows_listed_origins_only() {
+        let policy = policy("https://play.example.com/,http://localhost:5173").unwrap();
+        assert!(policy.allows("https://play.example.com"));
+        assert!(policy.allows("HTTPS://Play.Example.com"));
+        assert!(policy.allows("http://localhost:5173"));
+        assert!(!policy.allows("http://play.example.com"));
+        assert!(!policy.allows("https://play.example.com:8443"));
+        assert!(!policy.allows("http://localhost:5174"));
+        assert!(!policy.allows("null"));
+    }
+
+    #[test]
+    fn wildcards_only_match_subdomains() {
+        let policy = policy("https://*.example.com,https://*.example.org:8443").unwrap();
+        assert!(policy.allows("https://play.example.com"));
+        assert!(policy.allows("https://a.b.example.com"));
+        assert!(policy.allows("https://play.example.org:8443"));
+        assert!(!policy.allows("https://example.com"));
+        assert!(!policy.allows("https://evilexample.com"));
+        assert!(!policy.allows("https://.example.com"));
+        assert!(!policy.allows("https://play.example.org"));
+        assert!(!policy.allows("https://play.example.com.evil.net"));
+    }
+
+    #[test]
+    fn rejects_other_wildcards() {
+        for origin in [
+            "https://*example.com",
+            "https://play.*.example.com",
+            "https://*.",
+            "https://*.*.example.com",
+            "https://*",
+            "https://*.:8443",
+            "example.com",
+        ] {
+            assert!(policy(origin).is_err(), "{origin}");
+        }
+    }
+
+    #[test]
+    fn star_allows_any_origin() {
+        let policy = policy("*").unwrap();
+        assert!(policy.is_permissive());
+        assert!(policy.allows("https://anything.example"));
+    }
+
+    #[test]
+    fn same_host_is_always_allowed() {
+        let policy = policy("https://play.example.com").unwrap();
+        assert!(policy.allows_from(
+            "http://proxy.example.net:27016",
+            Some("proxy.example.net:27016")
+        ));
+        assert!(!policy.allows_from("https://evil.example", Some("proxy.example.net")));
+    }
+}
@@ -0,0 +1,365 @@
+//! Typed signaling protocol spoken over the `/ws` WebSocket.
+//!
+//! Every frame is a JSON object `{ "event": <name>, "data": <payload> }`.
+//! The client must open with `hello`; the proxy answers with its own `hello`
+//! and only then starts WebRTC negotiation. `client/src/protocol.ts` mirrors
+//! these types and must be kept in sync with this module.
+
+use serde::{Deserialize, Serialize};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+
+use crate::ice::IceServer;
+
+/// Signaling protocol version; bumped on any incompatible change
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features the proxy supports, advertised in its `hello`
+pub const SERVER_CAPABILITIES: &[&str] = &["status_events", "stats_events", "websocket_transport"];
+
+/// Messages sent by the browser
+#[derive(Debug, Deserialize)]
+#[serde(
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case",
+    deny_unknown_fields
+)]
+pub enum ClientMessage {
+    /// Opening handshake
+    Hello(ClientHello),
+    /// SDP answer to the proxy's offer
+    Answer(SessionDescription),
+    /// Trickled ICE candidate
+    Candidate(RTCIceCandidateInit),
+    /// Give up on WebRTC and carry game packets as binary WebSocket frames
+    UseWebsocket,
+}
+
+/// Messages sent by the proxy
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Handshake reply
+    Hello(ServerHello),
+    /// SDP offer for the game data channels
+    Offer(SessionDescription),
+    /// Trickled ICE candidate
+    Candidate(RTCIceCandidateInit),
+    /// UDP bridge to the game server is running
+    BridgeReady {
+        /// Name of the server the bridge is connected to
+        server: String,
+        /// Transport game packets are carried over
+        transport: TransportKind,
+    },
+    /// Game server refused packets or stayed silent
+    ServerUnreachable {
+        /// Human readable reason
+        message: String,
+    },
+    /// The proxy is shutting down; the session ends within the deadline
+    Draining {
+        /// Seconds until remaining bridges are closed
+        deadline_secs: u64,
+    },
+    /// Periodic traffic statistics of the running bridge
+    Stats(SessionStats),
+    /// Signaling or bridge setup failed
+    Error {
+        /// Stable machine readable code
+        code: ErrorCode,
+        /// Human readable description
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    /// Build an `error` message
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Client handshake payload
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientHello {
+    /// Protocol version the client speaks
+    pub version: u32,
+    /// Optional features the client supports
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Preferred data channel mode, overriding the proxy default
+    #[serde(default)]
+    pub channel_mode: Option<ChannelMode>,
+    /// Password or token, unless already passed as `?auth=`
+    #[serde(default)]
+    pub auth: Option<String>,
+}
+
+/// Proxy handshake payload
+#[derive(Debug, Serialize)]
+pub struct ServerHello {
+    /// Protocol version the proxy speaks
+    pub version: u32,
+    /// Optional features the proxy supports
+    pub capabilities: Vec<String>,
+    /// Name of the selected game server
+    pub server: String,
+    /// STUN/TURN servers the browser should use for its peer connection
+    pub ice_servers: Vec<IceServer>,
+    /// Delivery mode of the game data channels for this session
+    pub channel_mode: ChannelMode,
+}
+
+/// Traffic statistics of a running bridge
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStats {
+    /// Browser → game server
+    pub to_server: DirectionStats,
+    /// Game server → browser
+    pub to_client: DirectionStats,
+    /// Bytes queued in the outgoing data channel (WebRTC only)
+    pub buffered_amount: Option<usize>,
+}
+
+/// Traffic statistics of one bridge direction
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DirectionStats {
+    /// Packets forwarded
+    pub packets: u64,
+    /// Payload bytes forwarded
+    pub bytes: u64,
+    /// Smoothed variation of packet inter-arrival times in milliseconds
+    pub jitter_ms: f64,
+    /// Packets dropped by the rate limit
+    pub dropped: u64,
+}
+
+/// Delivery guarantees of the game data channels
+///
+/// `GoldSrc` runs its own sequencing and retransmission on top of UDP, so
+/// anything stronger than `unreliable` only adds head-of-line blocking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMode {
+    /// Ordered with unlimited retransmissions (plain SCTP)
+    Reliable,
+    /// Unordered with unlimited retransmissions
+    Unordered,
+    /// Unordered without retransmissions, closest to UDP
+    Unreliable,
+    /// Unordered, retransmitted until `--data-channel-lifetime` expires
+    Lifetime,
+}
+
+/// Transport carrying game packets between browser and proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    /// WebRTC data channels
+    Webrtc,
+    /// Binary frames on the signaling WebSocket
+    Websocket,
+    /// Datagrams on a WebTransport session
+    Webtransport,
+}
+
+impl TransportKind {
+    /// Name as used on the wire and in metric labels
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Webrtc => "webrtc",
+            Self::Websocket => "websocket",
+            Self::Webtransport => "webtransport",
+        }
+    }
+}
+
+/// SDP type of a session description
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SdpType {
+    /// SDP offer
+    Offer,
+    /// SDP answer
+    Answer,
+}
+
+/// Session description as produced by `RTCSessionDescription.toJSON()`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionDescription {
+    /// Offer or answer
+    #[serde(rename = "type")]
+    pub kind: SdpType,
+    /// Raw SDP
+    pub sdp: String,
+}
+
+/// Stable error codes sent to the browser in `error` events
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A signaling frame could not be parsed
+    InvalidMessage,
+    /// A message was sent before the `hello` handshake (or `hello` was repeated)
+    HandshakeRequired,
+    /// The client speaks a different protocol version
+    UnsupportedVersion,
+    /// The proxy could not create a WebRTC peer connection
+    PeerConnectionFailed,
+    /// The proxy could not create the game data channels
+    DataChannelFailed,
+    /// The proxy could not create or send its SDP offer
+    OfferFailed,
+    /// The SDP answer was rejected
+    InvalidAnswer,
+    /// An ICE candidate was rejected
+    InvalidCandidate,
+    /// ICE could not establish a connection
+    IceFailed,
+    /// The target policy refused the upstream server
+    TargetRefused,
+    /// The UDP bridge to the game server could not be created
+    BridgeFailed,
+    /// An administrator ended the session
+    Disconnected,
+    /// The proxy or the client's address reached its session limit
+    TooManySessions,
+    /// The session sent or received more than its rate limit allows
+    RateLimited,
+    /// Authentication is required and the credential was missing or rejected
+    Unauthorized,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn client(value: serde_json::Value) -> Result<ClientMessage, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    #[test]
+    fn parses_client_messages() {
+        let hello = client(json!({
+            "event": "hello",
+            "data": { "version": 1, "capabilities": ["stats_events"], "channel_mode": "lifetime", "auth": "pw" }
+        }))
+        .unwrap();
+        let ClientMessage::Hello(hello) = hello else {
+            panic!("expected hello, got {hello:?}");
+        };
+        assert_eq!(hello.version, PROTOCOL_VERSION);
+        assert_eq!(hello.capabilities, ["stats_events"]);
+        assert_eq!(hello.channel_mode, Some(ChannelMode::Lifetime));
+        assert_eq!(hello.auth.as_deref(), Some("pw"));
+
+        let answer =
+            client(json!({ "event": "answer", "data": { "type": "answer", "sdp": "v=0" } }));
+        assert!(matches!(
+            answer,
+            Ok(ClientMessage::Answer(SessionDescription {
+                kind: SdpType::Answer,
+                ..
+            }))
+        ));
+
+        let candidate = client(json!({
+            "event": "candidate",
+            "data": { "candidate": "candidate:1 1 udp 1 10.0.0.1 5000 typ host", "sdpMid": "0", "sdpMLineIndex": 0 }
+        }));
+        assert!(
+            matches!(candidate, Ok(ClientMessage::Candidate(c)) if c.sdp_mid.as_deref() == Some("0"))
+        );
+
+        assert!(matches!(
+            client(json!({ "event": "use_websocket" })),
+            Ok(ClientMessage::UseWebsocket)
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_client_messages() {
+        for value in [
+            json!({ "event": "hello", "data": { "version": 1, "extra": true } }),
+            json!({ "event": "hello", "data": {} }),
+            json!({ "event": "offer", "data": { "type": "offer", "sdp": "v=0" } }),
+            json!({ "event": "answer", "data": { "type": "pranswer", "sdp": "v=0" } }),
+            json!({ "data": { "version": 1 } }),
+        ] {
+            assert!(client(value.clone()).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn serializes_server_messages() {
+        let hello = ServerMessage::Hello(ServerHello {
+            version: PROTOCOL_VERSION,
+            capabilities: vec!["status_events".to_string()],
+            server: "default".to_string(),
+            ice_servers: vec![IceServer {
+                urls: vec!["stun:stun.example.com".to_string()],
+                username: None,
+                credential: None,
+            }],
+            channel_mode: ChannelMode::Unreliable,
+        });
+        assert_eq!(
+            serde_json::to_value(&hello).unwrap(),
+            json!({
+                "event": "hello",
+                "data": {
+                    "version": 1,
+                    "capabilities": ["status_events"],
+                    "server": "default",
+                    "ice_servers": [{ "urls": ["stun:stun.example.com"] }],
+                    "channel_mode": "unreliable"
+                }
+            })
+        );
+
+        let ready = ServerMessage::BridgeReady {
+            server: "default".to_string(),
+            transport: TransportKind::Webtransport,
+        };
+        assert_eq!(
+            serde_json::to_value(&ready).unwrap(),
+            json!({ "event": "bridge_ready", "data": { "server": "default", "transport": "webtransport" } })
+        );
+
+        assert_eq!(
+            serde_json::to_value(ServerMessage::error(ErrorCode::TargetRefused, "no")).unwrap(),
+            json!({ "event": "error", "data": { "code": "target_refused", "message": "no" } })
+        );
+    }
+
+    #[test]
+    fn session_descriptions_round_trip() {
+        let offer = json!({ "type": "offer", "sdp": "v=0\r\n" });
+        let parsed: SessionDescription = serde_json::from_value(offer.clone()).unwrap();
+        assert_eq!(parsed.kind, SdpType::Offer);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), offer);
+        assert_eq!(
+            serde_json::to_value(ServerMessage::Offer(parsed)).unwrap(),
+            json!({ "event": "offer", "data": offer })
+        );
+    }
+
+    #[test]
+    fn transport_names_match_the_wire() {
+        for kind in [
+            TransportKind::Webrtc,
+            TransportKind::Websocket,
+            TransportKind::Webtransport,
+        ] {
+            assert_eq!(serde_json::to_value(kind).unwrap(), json!(kind.as_str()));
+        }
+    }
+}
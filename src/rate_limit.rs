@@ -0,0 +1,170 @@
+//! Per-session packet and bandwidth limits.
+//!
+//! Each bridge direction gets a token bucket for packets and one for bytes,
+//! each holding one second worth of its rate. Game servers often trust the
+//! proxy's IP, so a modified client must not be able to flood them through it.
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// What happens to a packet over the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitPolicy {
+    /// Drop the packet
+    Drop,
+    /// Forward the packet but log the excess
+    Log,
+    /// Drop the packet and end the session
+    Disconnect,
+}
+
+/// Rates of one direction, 0 for unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionLimit {
+    /// Packets per second
+    pub packets_per_sec: u64,
+    /// Payload bytes per second
+    pub bytes_per_sec: u64,
+}
+
+/// Limits of both directions of a bridge
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// Browser → game server
+    pub to_server: DirectionLimit,
+    /// Game server → browser
+    pub to_client: DirectionLimit,
+    /// What happens to packets over the limit
+    pub policy: RateLimitPolicy,
+}
+
+impl RateLimits {
+    /// Limits from CLI options
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            to_server: DirectionLimit {
+                packets_per_sec: config.to_server_packets_per_sec,
+                bytes_per_sec: config.to_server_bytes_per_sec,
+            },
+            to_client: DirectionLimit {
+                packets_per_sec: config.to_client_packets_per_sec,
+                bytes_per_sec: config.to_client_bytes_per_sec,
+            },
+            policy: config.rate_limit_policy,
+        }
+    }
+}
+
+/// Packet and byte buckets of one bridge direction
+pub struct Throttle {
+    packets: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Throttle {
+    /// Create full buckets; a zero rate is not limited
+    pub fn new(limit: DirectionLimit) -> Self {
+        Self {
+            packets: TokenBucket::new(limit.packets_per_sec),
+            bytes: TokenBucket::new(limit.bytes_per_sec),
+        }
+    }
+
+    /// Whether a packet of `len` bytes is within the limits
+    ///
+    /// Tokens are only taken when both buckets admit the packet.
+    #[allow(clippy::cast_precision_loss)] // packets are at most 64 KiB
+    pub fn admit(&mut self, len: usize) -> bool {
+        let now = Instant::now();
+        let len = len as f64;
+        let fits = |bucket: &mut Option<TokenBucket>, cost: f64| {
+            bucket.as_mut().is_none_or(|b| {
+                b.refill(now);
+                b.tokens >= cost
+            })
+        };
+
+        if !(fits(&mut self.packets, 1.0) && fits(&mut self.bytes, len)) {
+            return false;
+        }
+        if let Some(b) = &mut self.packets {
+            b.tokens -= 1.0;
+        }
+        if let Some(b) = &mut self.bytes {
+            b.tokens -= len;
+        }
+        true
+    }
+}
+
+/// Bucket refilled at `rate` tokens per second up to one second worth
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    #[allow(clippy::cast_precision_loss)] // rates stay far below 2^52
+    fn new(rate: u64) -> Option<Self> {
+        (rate > 0).then(|| Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: Instant::now(),
+        })
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn throttle(packets_per_sec: u64, bytes_per_sec: u64) -> Throttle {
+        Throttle::new(DirectionLimit {
+            packets_per_sec,
+            bytes_per_sec,
+        })
+    }
+
+    #[test]
+    fn admits_one_second_worth_of_packets() {
+        let mut throttle = throttle(10, 0);
+        assert_eq!((0..20).filter(|_| throttle.admit(1400)).count(), 10);
+    }
+
+    #[test]
+    fn byte_limit_does_not_charge_rejected_packets() {
+        let mut throttle = throttle(100, 1000);
+        assert!(throttle.admit(600));
+        // Over the byte budget; neither bucket is charged
+        assert!(!throttle.admit(600));
+        assert!(throttle.admit(400));
+        assert!(!throttle.admit(100));
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let mut throttle = throttle(1000, 0);
+        while throttle.admit(1) {}
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(throttle.admit(1));
+    }
+
+    #[test]
+    fn zero_rates_are_unlimited() {
+        let mut throttle = throttle(0, 0);
+        assert!((0..10_000).all(|_| throttle.admit(65_535)));
+    }
+}
@@ -0,0 +1,256 @@
+//! Configuration reload without restarting.
+//!
+//! The `--config` and `--servers-file` files are checked for changes every
+//! few seconds, and `SIGHUP` reloads on demand. The new configuration applies
+//! to sessions started afterwards; running sessions keep their bridge and
+//! upstream. Settings bound at startup, like listeners, TLS and the embedded
+//! TURN server, keep their value until the next restart.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::config::{CommandLine, Config, SECRET_SETTINGS};
+use crate::servers::ServerRegistry;
+use crate::state::{AppState, Settings};
+use crate::turn_server::TurnRelay;
+
+/// How often the configuration files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings bound at startup, which only a restart changes
+const RESTART_SETTINGS: [&str; 27] = [
+    "port",
+    "host",
+    "tls_cert",
+    "tls_key",
+    "tls_reload_secs",
+    "tls_self_signed",
+    "tls_self_signed_name",
+    "webtransport_port",
+    "webtransport_cert",
+    "webtransport_key",
+    "turn_listen_port",
+    "turn_relay_ip",
+    "turn_relay_ports",
+    "turn_allow_private_peers",
+    "turn_realm",
+    "ice_udp_port",
+    "ice_port_range",
+    "master_server",
+    "master_filter",
+    "master_max_servers",
+    "server_list_file",
+    "server_browser_concurrency",
+    "server_browser_refresh_secs",
+    "server_info_cache_secs",
+    "shutdown_timeout",
+    "static_dir",
+    "verbose",
+];
+
+/// Reload on file changes and `SIGHUP`, forever
+///
+/// The command line parsed at startup is merged over every reloaded file.
+pub async fn watch(state: AppState, command_line: CommandLine) {
+    let mut hangup = Hangup::new();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    let mut files = modified(&state.settings().config).await;
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                if modified(&state.settings().config).await == files {
+                    continue;
+                }
+                info!("Configuration file changed, reloading");
+            }
+            () = hangup.recv() => info!("SIGHUP received, reloading configuration"),
+        }
+
+        if let Err(e) = reload(&state, &command_line).await {
+            error!(error = %e, "Configuration reload failed, keeping the current configuration");
+        }
+        files = modified(&state.settings().config).await;
+    }
+}
+
+/// Load the configuration again and swap it in if it is valid
+async fn reload(
+    state: &AppState,
+    command_line: &CommandLine,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current = state.settings();
+    let (command_line, turn_relay) = (command_line.clone(), state.turn_relay.clone());
+    let running = current.config.clone();
+    let loaded = tokio::task::spawn_blocking(move || {
+        load(&running, &command_line, turn_relay).map_err(|e| e.to_string())
+    })
+    .await??;
+    state.limits.reconfigure(&loaded.settings.config)?;
+
+    let changes = log_changes(&loaded.running, &loaded.requested)
+        + log_server_changes(&current.servers, &loaded.settings.servers)?;
+    state.replace_settings(loaded.settings);
+    info!(changes, "Configuration reloaded");
+    Ok(())
+}
+
+/// Settings built from the reloaded files, with the settings tables they
+/// are logged from
+struct Loaded {
+    settings: Settings,
+    running: toml::Table,
+    requested: toml::Table,
+}
+
+/// Read the configuration files and build settings that keep every
+/// [`RESTART_SETTINGS`] entry of the running configuration
+///
+/// This reads files with blocking IO.
+fn load(
+    current: &Config,
+    command_line: &CommandLine,
+    turn_relay: Option<Arc<TurnRelay>>,
+) -> Result<Loaded, Box<dyn std::error::Error>> {
+    let loaded = command_line.load()?;
+    let running = to_table(current)?;
+    let requested = to_table(&loaded)?;
+    let mut table = requested.clone();
+    for key in RESTART_SETTINGS {
+        match running.get(key) {
+            Some(value) => table.insert(key.to_string(), value.clone()),
+            None => table.remove(key),
+        };
+    }
+    let mut config: Config = table.try_into()?;
+    config.settings_file = loaded.settings_file;
+
+    Ok(Loaded {
+        settings: Settings::new(config, turn_relay)?,
+        running,
+        requested,
+    })
+}
+
+/// Log every changed setting, returning how many changed
+///
+/// Servers are compared by name in [`log_server_changes`] instead.
+fn log_changes(old: &toml::Table, new: &toml::Table) -> usize {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut changes = 0;
+    for key in keys.into_iter().filter(|key| *key != "servers") {
+        let (before, after) = (old.get(key), new.get(key));
+        if before == after {
+            continue;
+        }
+        changes += 1;
+
+        // Values of secrets, and of ICE servers with their TURN credentials,
+        // stay out of the log
+        let (before, after) = if SECRET_SETTINGS.contains(&key.as_str()) || key == "ice_servers" {
+            ("<redacted>".to_string(), "<redacted>".to_string())
+        } else {
+            (show(before), show(after))
+        };
+        if RESTART_SETTINGS.contains(&key.as_str()) {
+            warn!(setting = %key, old = %before, new = %after, "Setting changed, takes effect after a restart");
+        } else {
+            info!(setting = %key, old = %before, new = %after, "Setting changed");
+        }
+    }
+    changes
+}
+
+/// Log added, removed and changed servers, returning how many changed
+fn log_server_changes(
+    old: &ServerRegistry,
+    new: &ServerRegistry,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut changes = 0;
+    for server in new.iter() {
+        let Some(previous) = old.get(Some(&server.name)) else {
+            info!(server = %server.name, address = %server.address, "Server added");
+            changes += 1;
+            continue;
+        };
+
+        let (before, after) = (to_table(previous)?, to_table(server)?);
+        let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        for key in keys {
+            let (old_value, new_value) = (before.get(key), after.get(key));
+            if old_value != new_value {
+                info!(
+                    server = %server.name,
+                    setting = %key,
+                    old = %show(old_value),
+                    new = %show(new_value),
+                    "Server setting changed"
+                );
+                changes += 1;
+            }
+        }
+    }
+    for server in old.iter() {
+        if new.get(Some(&server.name)).is_none() {
+            info!(server = %server.name, "Server removed");
+            changes += 1;
+        }
+    }
+    Ok(changes)
+}
+
+/// Setting value as written in TOML
+fn show(value: Option<&toml::Value>) -> String {
+    value.map_or_else(|| "unset".to_string(), ToString::to_string)
+}
+
+/// Serialize a struct into a TOML table
+fn to_table(value: &impl Serialize) -> Result<toml::Table, toml::ser::Error> {
+    match toml::Value::try_from(value)? {
+        toml::Value::Table(table) => Ok(table),
+        _ => unreachable!("structs serialize to tables"),
+    }
+}
+
+/// Modification times of the configuration files
+async fn modified(config: &Config) -> Vec<Option<SystemTime>> {
+    let mut times = Vec::new();
+    for path in [&config.settings_file, &config.servers_file]
+        .into_iter()
+        .flatten()
+    {
+        let metadata = tokio::fs::metadata(path).await;
+        times.push(metadata.and_then(|m| m.modified()).ok());
+    }
+    times
+}
+
+/// `SIGHUP` listener; never fires where there is no such signal
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .inspect_err(|e| warn!(error = %e, "Failed to listen for SIGHUP"))
+                .ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await;
+    }
+}
@@ -0,0 +1,430 @@
+//! Server browser backed by a master server and/or a static list.
+//!
+//! Addresses come from the configured servers, `--server-list-file` and a
+//! Valve-style master server (`--master-server`). Every refresh queries all of
+//! them with `A2S_INFO`, at most `--server-browser-concurrency` at a time, and
+//! `/api/servers` serves the answers sorted and filtered.
+
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::a2s::{self, ServerInfo};
+use crate::config::Config;
+use crate::servers::ServerRegistry;
+use crate::state::AppState;
+
+/// How long one `A2S_INFO` query may take
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for each master server reply
+const MASTER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often a master server page is requested before giving up
+const MASTER_ATTEMPTS: usize = 3;
+
+/// Master server reply header: single packet, `f`, newline
+const MASTER_REPLY: [u8; 6] = [0xFF, 0xFF, 0xFF, 0xFF, 0x66, 0x0A];
+
+/// Region code for "all regions"
+const REGION_ALL: u8 = 0xFF;
+
+/// Seed address that starts and ends a master server listing
+const MASTER_SEED: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+/// One answering server in the browser
+#[derive(Debug, Clone, Serialize)]
+pub struct BrowserEntry {
+    /// `host:port` of the game server
+    pub address: String,
+    /// Name of the configured server entry, if it is one
+    pub name: Option<String>,
+    /// Page that plays on this server, if the proxy may connect to it
+    pub join: Option<String>,
+    /// Round trip time of the `A2S_INFO` query
+    pub ping_ms: f64,
+    /// `A2S_INFO` answer
+    pub info: ServerInfo,
+}
+
+/// Sort order of `/api/servers`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// Most players first
+    #[default]
+    Players,
+    /// Server name, alphabetically
+    Name,
+    /// Map name, alphabetically
+    Map,
+    /// Lowest ping first
+    Ping,
+}
+
+/// Filters and sorting of `/api/servers`
+// Flat query parameters, each flag independent of the others
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Deserialize)]
+pub struct BrowserQuery {
+    /// Case-insensitive substring of the server name
+    pub search: Option<String>,
+    /// Exact map name
+    pub map: Option<String>,
+    /// Game directory, e.g. "cstrike"
+    pub folder: Option<String>,
+    /// Hide servers without players
+    #[serde(default)]
+    pub not_empty: bool,
+    /// Hide full servers
+    #[serde(default)]
+    pub not_full: bool,
+    /// Hide password protected servers
+    #[serde(default)]
+    pub no_password: bool,
+    /// Sort key
+    #[serde(default)]
+    pub sort: SortKey,
+    /// Reverse the sort order
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+/// Periodically refreshed list of game servers
+pub struct ServerBrowser {
+    master_server: Option<String>,
+    master_filter: String,
+    master_max_servers: usize,
+    list_file: Option<String>,
+    concurrency: usize,
+    refresh_interval: Duration,
+    entries: RwLock<Vec<BrowserEntry>>,
+}
+
+impl ServerBrowser {
+    /// Create the browser from CLI options; nothing is queried until [`Self::run`]
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            master_server: config.master_server.clone(),
+            master_filter: config.master_filter.clone(),
+            master_max_servers: config.master_max_servers,
+            list_file: config.server_list_file.clone(),
+            concurrency: config.server_browser_concurrency.max(1),
+            refresh_interval: Duration::from_secs(config.server_browser_refresh_secs.max(1)),
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Refresh the list forever, with the servers configured at each refresh
+    pub async fn run(self: Arc<Self>, state: AppState) {
+        let mut interval = tokio::time::interval(self.refresh_interval);
+        loop {
+            interval.tick().await;
+            self.refresh(&state).await;
+        }
+    }
+
+    /// Filtered and sorted snapshot of the last refresh
+    pub fn list(&self, query: &BrowserQuery) -> Vec<BrowserEntry> {
+        let search = query.search.as_deref().map(str::to_lowercase);
+        let mut entries: Vec<_> = self
+            .entries
+            .read()
+            .expect("server browser lock poisoned")
+            .iter()
+            .filter(|e| {
+                let info = &e.info;
+                search
+                    .as_deref()
+                    .is_none_or(|s| info.name.to_lowercase().contains(s))
+                    && query.map.as_deref().is_none_or(|m| info.map == m)
+                    && query.folder.as_deref().is_none_or(|f| info.folder == f)
+                    && (!query.not_empty || info.players > 0)
+                    && (!query.not_full || info.players < info.max_players)
+                    && (!query.no_password || !info.password)
+            })
+            .cloned()
+            .collect();
+
+        match query.sort {
+            SortKey::Players => entries.sort_by_key(|e| std::cmp::Reverse(e.info.players)),
+            SortKey::Name => entries.sort_by_cached_key(|e| e.info.name.to_lowercase()),
+            SortKey::Map => entries.sort_by(|a, b| a.info.map.cmp(&b.info.map)),
+            SortKey::Ping => entries.sort_by(|a, b| a.ping_ms.total_cmp(&b.ping_ms)),
+        }
+        if query.reverse {
+            entries.reverse();
+        }
+        entries
+    }
+
+    /// Query every known address once and replace the list
+    async fn refresh(&self, state: &AppState) {
+        let settings = state.settings();
+        let addresses = self.addresses(&settings.servers).await;
+        let total = addresses.len();
+        let allow_custom_targets = settings.config.allow_custom_targets;
+
+        let entries: Vec<BrowserEntry> = stream::iter(addresses)
+            .map(|(address, name)| Self::query_entry(address, name, allow_custom_targets))
+            .buffer_unordered(self.concurrency)
+            .filter_map(|entry| async move { entry })
+            .collect()
+            .await;
+
+        debug!(answered = entries.len(), total, "Server browser refreshed");
+        *self.entries.write().expect("server browser lock poisoned") = entries;
+    }
+
+    /// Configured, listed and master server addresses without duplicates
+    ///
+    /// Configured servers keep their entry name.
+    async fn addresses(&self, servers: &ServerRegistry) -> Vec<(String, Option<String>)> {
+        let mut seen = HashSet::new();
+        let mut addresses = Vec::new();
+
+        for server in servers.iter() {
+            if seen.insert(server.address.clone()) {
+                addresses.push((server.address.clone(), Some(server.name.clone())));
+            }
+        }
+
+        let mut discovered = Vec::new();
+        if let Some(path) = &self.list_file {
+            match read_list_file(path).await {
+                Ok(list) => discovered.extend(list),
+                Err(e) => warn!(path, error = %e, "Failed to read server list file"),
+            }
+        }
+        if let Some(master) = &self.master_server {
+            match query_master(master, &self.master_filter, self.master_max_servers).await {
+                Ok(list) => {
+                    info!(master, servers = list.len(), "Master server listed servers");
+                    discovered.extend(list.into_iter().map(|addr| addr.to_string()));
+                }
+                Err(e) => warn!(master, error = %e, "Master server query failed"),
+            }
+        }
+
+        for address in discovered {
+            if seen.insert(address.clone()) {
+                addresses.push((address, None));
+            }
+        }
+        addresses
+    }
+
+    /// Query one server; `None` if it does not answer
+    async fn query_entry(
+        address: String,
+        name: Option<String>,
+        allow_custom_targets: bool,
+    ) -> Option<BrowserEntry> {
+        let started = Instant::now();
+        let info = match a2s::info(&address, QUERY_TIMEOUT).await {
+            Ok(info) => info,
+            Err(e) => {
+                debug!(address, error = %e, "Server did not answer A2S_INFO");
+                return None;
+            }
+        };
+        let ping_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let join = match &name {
+            Some(name) => Some(format!("/play/{name}")),
+            None if allow_custom_targets => Some(format!("/?target={address}")),
+            None => None,
+        };
+
+        Some(BrowserEntry {
+            address,
+            name,
+            join,
+            ping_ms,
+            info,
+        })
+    }
+}
+
+/// Read a JSON array of `host:port` strings
+async fn read_list_file(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// List servers from a Valve master server
+///
+/// Pages through the listing by sending the last address of each reply as the
+/// next seed until the master sends the `0.0.0.0:0` terminator.
+async fn query_master(
+    master: &str,
+    filter: &str,
+    max_servers: usize,
+) -> Result<Vec<SocketAddrV4>, Box<dyn std::error::Error>> {
+    let target = tokio::net::lookup_host(master)
+        .await?
+        .next()
+        .ok_or("master server address did not resolve")?;
+    let socket = UdpSocket::bind(match target {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })
+    .await?;
+    socket.connect(target).await?;
+
+    let mut servers = Vec::new();
+    let mut seed = MASTER_SEED;
+    let mut buf = vec![0u8; 2048];
+
+    while servers.len() < max_servers {
+        let mut request = vec![b'1', REGION_ALL];
+        request.extend_from_slice(seed.to_string().as_bytes());
+        request.push(0);
+        request.extend_from_slice(filter.as_bytes());
+        request.push(0);
+
+        // Requests and replies are plain UDP, so resend a lost page
+        let mut reply = None;
+        for _ in 0..MASTER_ATTEMPTS {
+            socket.send(&request).await?;
+            if let Ok(n) = tokio::time::timeout(MASTER_TIMEOUT, socket.recv(&mut buf)).await {
+                reply = Some(n?);
+                break;
+            }
+        }
+        let n = reply.ok_or("master server did not respond")?;
+        let body = buf[..n]
+            .strip_prefix(&MASTER_REPLY)
+            .ok_or("unexpected master server reply")?;
+
+        let mut last = None;
+        for entry in body.chunks_exact(6) {
+            let addr = SocketAddrV4::new(
+                Ipv4Addr::new(entry[0], entry[1], entry[2], entry[3]),
+                u16::from_be_bytes([entry[4], entry[5]]),
+            );
+            if addr == MASTER_SEED {
+                return Ok(servers);
+            }
+            servers.push(addr);
+            last = Some(addr);
+        }
+
+        match last {
+            Some(addr) if addr != seed => seed = addr,
+            // Empty or repeated page: the master has nothing more
+            _ => break,
+        }
+    }
+
+    servers.truncate(max_servers);
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Master server reply listing `addresses`
+    fn page(addresses: &[&str]) -> Vec<u8> {
+        let mut reply = MASTER_REPLY.to_vec();
+        for address in addresses {
+            let address: SocketAddrV4 = address.parse().unwrap();
+            reply.extend_from_slice(&address.ip().octets());
+            reply.extend_from_slice(&address.port().to_be_bytes());
+        }
+        reply
+    }
+
+    /// Fake master answering each expected seed with its page
+    async fn fake_master(pages: Vec<(&'static str, Vec<u8>)>) -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            for (seed, reply) in pages {
+                let (n, client) = socket.recv_from(&mut buf).await.unwrap();
+                let mut expected = vec![b'1', REGION_ALL];
+                expected.extend_from_slice(seed.as_bytes());
+                expected.extend_from_slice(b"\0\\gamedir\\cstrike\0");
+                assert_eq!(&buf[..n], &expected[..], "request for seed {seed}");
+                socket.send_to(&reply, client).await.unwrap();
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_master_listing() {
+        let master = fake_master(vec![
+            (
+                "0.0.0.0:0",
+                page(&["203.0.113.1:27015", "203.0.113.2:27016"]),
+            ),
+            (
+                "203.0.113.2:27016",
+                page(&["198.51.100.7:27015", "0.0.0.0:0"]),
+            ),
+        ])
+        .await;
+
+        let servers = query_master(&master, "\\gamedir\\cstrike", 100)
+            .await
+            .unwrap();
+        let servers: Vec<String> = servers.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            servers,
+            [
+                "203.0.113.1:27015",
+                "203.0.113.2:27016",
+                "198.51.100.7:27015"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_servers() {
+        let master = fake_master(vec![(
+            "0.0.0.0:0",
+            page(&[
+                "203.0.113.1:27015",
+                "203.0.113.2:27015",
+                "203.0.113.3:27015",
+            ]),
+        )])
+        .await;
+
+        let servers = query_master(&master, "\\gamedir\\cstrike", 2)
+            .await
+            .unwrap();
+        assert_eq!(servers.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_on_a_repeated_page() {
+        let master = fake_master(vec![
+            ("0.0.0.0:0", page(&["203.0.113.1:27015"])),
+            ("203.0.113.1:27015", page(&["203.0.113.1:27015"])),
+        ])
+        .await;
+
+        let servers = query_master(&master, "\\gamedir\\cstrike", 100)
+            .await
+            .unwrap();
+        assert_eq!(servers.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_replies() {
+        let master = fake_master(vec![("0.0.0.0:0", b"\xFF\xFF\xFF\xFFI".to_vec())]).await;
+        assert!(query_master(&master, "\\gamedir\\cstrike", 100)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Cached A2S details of the configured game servers.
+//!
+//! Served on `/api/server/info` so the web client can show map, players and
+//! server settings before joining. Each server is queried at most once per
+//! `--server-info-cache-secs`, however many browsers ask.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use crate::a2s::{self, A2sError, PlayerInfo, ServerInfo};
+use crate::servers::ServerEntry;
+
+/// How long a single A2S query may take
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Everything the game server reports about itself
+#[derive(Debug, Serialize)]
+pub struct ServerDetails {
+    /// Name of the configured server entry
+    pub server: String,
+    /// `A2S_INFO` answer
+    pub info: ServerInfo,
+    /// Connected players, `None` if the server does not answer `A2S_PLAYER`
+    pub players: Option<Vec<PlayerInfo>>,
+    /// Server cvars, `None` if the server does not answer `A2S_RULES`
+    pub rules: Option<BTreeMap<String, String>>,
+}
+
+/// Last successful answer of one server
+type CacheSlot = Arc<Mutex<Option<(Instant, Arc<ServerDetails>)>>>;
+
+/// Per-server cache of A2S answers
+pub struct ServerInfoCache {
+    ttl: Duration,
+    entries: DashMap<String, CacheSlot>,
+}
+
+impl ServerInfoCache {
+    /// Create an empty cache keeping answers for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Cached details of a server, querying it if the cache is stale
+    ///
+    /// Concurrent callers for the same server wait for a single query.
+    pub async fn get(&self, server: &ServerEntry) -> Result<Arc<ServerDetails>, A2sError> {
+        let slot = self.entries.entry(server.name.clone()).or_default().clone();
+        let mut cached = slot.lock().await;
+
+        if let Some((fetched_at, details)) = cached.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(details.clone());
+            }
+        }
+
+        let details = Arc::new(query(server).await?);
+        *cached = Some((Instant::now(), details.clone()));
+        Ok(details)
+    }
+}
+
+/// Query info, players and rules of a server concurrently
+async fn query(server: &ServerEntry) -> Result<ServerDetails, A2sError> {
+    let (info, players, rules) = tokio::join!(
+        a2s::info(&server.address, QUERY_TIMEOUT),
+        a2s::players(&server.address, QUERY_TIMEOUT),
+        a2s::rules(&server.address, QUERY_TIMEOUT),
+    );
+
+    let players = players
+        .inspect_err(|e| debug!(server = %server.name, error = %e, "A2S_PLAYER failed"))
+        .ok();
+    let rules = rules
+        .inspect_err(|e| debug!(server = %server.name, error = %e, "A2S_RULES failed"))
+        .ok();
+
+    Ok(ServerDetails {
+        server: server.name.clone(),
+        info: info?,
+        players,
+        rules,
+    })
+}
@@ -0,0 +1,117 @@
+//! Registry of upstream game servers the proxy can route clients to.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Name of the server entry created from `--server`
+pub const DEFAULT_SERVER_NAME: &str = "default";
+
+/// A single named game server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerEntry {
+    /// Unique name used in `/play/<name>` and signaling
+    pub name: String,
+    /// Game server address (e.g., 192.168.1.100:27015)
+    pub address: String,
+    /// Game directory name (e.g., "cstrike", "valve")
+    #[serde(default = "default_game_dir")]
+    pub game_dir: String,
+    /// Console commands to execute on client start
+    #[serde(default)]
+    pub console_commands: Vec<String>,
+    /// Extra `files_map` entries of the web client, overriding the defaults
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files_map: BTreeMap<String, String>,
+}
+
+fn default_game_dir() -> String {
+    "cstrike".to_string()
+}
+
+/// Lookup table of all configured game servers
+#[derive(Debug, Clone)]
+pub struct ServerRegistry {
+    /// Entries in configuration order
+    entries: Vec<ServerEntry>,
+    /// Index of each entry by name
+    by_name: HashMap<String, usize>,
+}
+
+impl ServerRegistry {
+    /// Build the registry from `--server`, `--servers-file` and the `servers`
+    /// of the configuration file
+    ///
+    /// The `--server` entry (if any) is registered as [`DEFAULT_SERVER_NAME`]
+    /// and is always the first, and therefore default, entry.
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = Vec::new();
+
+        if let Some(ref address) = config.server {
+            entries.push(ServerEntry {
+                name: DEFAULT_SERVER_NAME.to_string(),
+                address: address.clone(),
+                game_dir: config.game_dir.clone(),
+                console_commands: config.get_console_commands(),
+                files_map: BTreeMap::new(),
+            });
+        }
+
+        if let Some(ref path) = config.servers_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read servers file {path}: {e}"))?;
+            let file_entries: Vec<ServerEntry> = serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse servers file {path}: {e}"))?;
+            entries.extend(file_entries);
+        }
+        entries.extend(config.servers.iter().cloned());
+
+        Self::new(entries)
+    }
+
+    /// Build the registry from a list of entries
+    pub fn new(entries: Vec<ServerEntry>) -> Result<Self, Box<dyn std::error::Error>> {
+        if entries.is_empty() {
+            return Err("No game servers configured (use --server, --servers-file or [[servers]] in --config)".into());
+        }
+
+        let mut by_name = HashMap::new();
+        for (index, entry) in entries.iter().enumerate() {
+            if !is_valid_name(&entry.name) {
+                return Err(format!(
+                    "Invalid server name {:?} (use letters, digits, '-' and '_')",
+                    entry.name
+                )
+                .into());
+            }
+            if by_name.insert(entry.name.clone(), index).is_some() {
+                return Err(format!("Duplicate server name {:?}", entry.name).into());
+            }
+        }
+
+        Ok(Self { entries, by_name })
+    }
+
+    /// Get a server by name, or the default server when no name is given
+    pub fn get(&self, name: Option<&str>) -> Option<&ServerEntry> {
+        match name {
+            Some(name) => self.by_name.get(name).map(|&index| &self.entries[index]),
+            None => self.entries.first(),
+        }
+    }
+
+    /// Iterate over all servers in configuration order
+    pub fn iter(&self) -> impl Iterator<Item = &ServerEntry> {
+        self.entries.iter()
+    }
+}
+
+/// Server names end up in URLs, so keep them to a URL-safe alphabet
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -0,0 +1,194 @@
+//! Registry of live browser sessions for the admin API.
+//!
+//! Every signaling WebSocket and WebTransport session registers itself here
+//! for its whole lifetime, so admins can list sessions and disconnect one.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::Notify;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+
+use crate::bridge::Bridge;
+use crate::protocol::{SessionStats, TransportKind};
+
+/// One live browser session
+pub struct Session {
+    client_id: String,
+    server: String,
+    remote_addr: SocketAddr,
+    client_ip: IpAddr,
+    started_at: Instant,
+    /// Authenticated player identity
+    identity: Mutex<Option<String>>,
+    /// WebRTC peer connection state, if the session negotiates one
+    peer_state: Mutex<Option<RTCPeerConnectionState>>,
+    /// Bridge of the session once it is running
+    bridge: Mutex<Weak<Bridge>>,
+    /// Signalled when an admin disconnects the session
+    disconnect: Notify,
+}
+
+impl Session {
+    /// Record a peer connection state change
+    pub fn set_peer_state(&self, state: RTCPeerConnectionState) {
+        *self.peer_state.lock().expect("session lock poisoned") = Some(state);
+    }
+
+    /// Record who the session authenticated as
+    pub fn set_identity(&self, identity: String) {
+        *self.identity.lock().expect("session lock poisoned") = Some(identity);
+    }
+
+    /// Browser IP of the session
+    pub fn client_ip(&self) -> IpAddr {
+        self.client_ip
+    }
+
+    /// Attach the running bridge
+    pub fn set_bridge(&self, bridge: &Arc<Bridge>) {
+        *self.bridge.lock().expect("session lock poisoned") = Arc::downgrade(bridge);
+    }
+
+    /// Wait until an admin disconnects the session
+    pub async fn disconnected(&self) {
+        self.disconnect.notified().await;
+    }
+
+    /// Snapshot for the admin API
+    async fn info(&self) -> SessionInfo {
+        let bridge = self.bridge.lock().expect("session lock poisoned").upgrade();
+        let stats = match &bridge {
+            Some(b) => Some(b.stats().await),
+            None => None,
+        };
+
+        SessionInfo {
+            client_id: self.client_id.clone(),
+            server: self.server.clone(),
+            remote_addr: self.remote_addr,
+            client_ip: self.client_ip,
+            identity: self.identity.lock().expect("session lock poisoned").clone(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            transport: bridge.as_ref().map(|b| b.transport_kind()),
+            upstream_local_port: bridge.as_ref().map(|b| b.local_port()),
+            stats,
+            peer_state: self
+                .peer_state
+                .lock()
+                .expect("session lock poisoned")
+                .map(|s| s.to_string()),
+        }
+    }
+}
+
+/// Session as listed by the admin API
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    /// Client identifier used in logs
+    pub client_id: String,
+    /// Name of the game server the session plays on
+    pub server: String,
+    /// Address the connection came from
+    pub remote_addr: SocketAddr,
+    /// Browser IP, taken from the client IP header behind a trusted proxy
+    pub client_ip: IpAddr,
+    /// Authenticated player identity, if authentication is enabled
+    pub identity: Option<String>,
+    /// Seconds since the session connected
+    pub uptime_secs: u64,
+    /// Transport of the running bridge
+    pub transport: Option<TransportKind>,
+    /// Local UDP port of the bridge socket towards the game server
+    pub upstream_local_port: Option<u16>,
+    /// Traffic statistics of the running bridge
+    pub stats: Option<SessionStats>,
+    /// WebRTC peer connection state
+    pub peer_state: Option<String>,
+}
+
+/// All live sessions by client ID
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: DashMap<String, Arc<Session>>,
+}
+
+impl SessionRegistry {
+    /// Register a new session; it stays listed until [`Self::remove`]
+    pub fn register(
+        &self,
+        client_id: &str,
+        server: &str,
+        remote_addr: SocketAddr,
+        browser_ip: IpAddr,
+    ) -> Arc<Session> {
+        let session = Arc::new(Session {
+            client_id: client_id.to_string(),
+            server: server.to_string(),
+            remote_addr,
+            client_ip: browser_ip,
+            identity: Mutex::new(None),
+            started_at: Instant::now(),
+            peer_state: Mutex::new(None),
+            bridge: Mutex::new(Weak::new()),
+            disconnect: Notify::new(),
+        });
+        self.sessions.insert(client_id.to_string(), session.clone());
+        session
+    }
+
+    /// Remove a session when it ends
+    pub fn remove(&self, client_id: &str) {
+        self.sessions.remove(client_id);
+    }
+
+    /// Snapshot of all sessions, oldest first
+    pub async fn list(&self) -> Vec<SessionInfo> {
+        // Collect first so no map shard stays locked across an await
+        let live: Vec<_> = self.sessions.iter().map(|s| s.value().clone()).collect();
+        let mut sessions = Vec::with_capacity(live.len());
+        for session in live {
+            sessions.push(session.info().await);
+        }
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.uptime_secs));
+        sessions
+    }
+
+    /// Number of live sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Whether no session is live
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Shut down the bridges of all live sessions; returns how many were live
+    pub fn shutdown_bridges(&self) -> usize {
+        for session in &self.sessions {
+            let bridge = session
+                .bridge
+                .lock()
+                .expect("session lock poisoned")
+                .upgrade();
+            if let Some(bridge) = bridge {
+                bridge.shutdown();
+            }
+        }
+        self.sessions.len()
+    }
+
+    /// Ask a session to disconnect; returns `false` if it does not exist
+    pub fn disconnect(&self, client_id: &str) -> bool {
+        let Some(session) = self.sessions.get(client_id) else {
+            return false;
+        };
+        // A stored permit also covers a session not yet waiting for it
+        session.disconnect.notify_one();
+        true
+    }
+}
@@ -0,0 +1,111 @@
+//! Graceful shutdown with connection draining.
+//!
+//! On SIGTERM or Ctrl-C the proxy stops accepting new sessions, reports
+//! draining on `/health`, tells connected browsers, and waits up to
+//! `--shutdown-timeout` for sessions to end before shutting down the
+//! remaining bridges.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::state::AppState;
+
+/// How often the drain checks whether all sessions have ended
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Draining state shared by handlers and sessions
+pub struct Shutdown {
+    draining: watch::Sender<bool>,
+    /// How long sessions may keep playing once draining started
+    timeout: Duration,
+}
+
+impl Shutdown {
+    /// Create the shutdown state with the drain deadline
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            draining: watch::Sender::new(false),
+            timeout,
+        }
+    }
+
+    /// Whether the proxy is shutting down and refuses new sessions
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Wait until draining starts
+    pub async fn draining(&self) {
+        let mut rx = self.draining.subscribe();
+        let _ = rx.wait_for(|draining| *draining).await;
+    }
+
+    /// Time sessions are given to finish
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// Wait for a termination signal, then drain sessions
+///
+/// Resolves once all sessions ended or the deadline passed, at which point the
+/// HTTP server stops.
+pub async fn drain_on_signal(state: AppState) {
+    wait_for_signal().await;
+
+    let shutdown = &state.shutdown;
+    shutdown.draining.send_replace(true);
+    info!(
+        sessions = state.sessions.len(),
+        timeout_secs = shutdown.timeout.as_secs(),
+        "Shutdown requested, draining sessions"
+    );
+
+    let deadline = tokio::time::Instant::now() + shutdown.timeout;
+    let mut poll = tokio::time::interval(DRAIN_POLL_INTERVAL);
+    while !state.sessions.is_empty() && tokio::time::Instant::now() < deadline {
+        poll.tick().await;
+    }
+
+    let remaining = state.sessions.shutdown_bridges();
+    if remaining > 0 {
+        warn!(
+            sessions = remaining,
+            "Drain deadline reached, shutting down bridges"
+        );
+    }
+    info!("Shutting down");
+}
+
+/// Resolve on Ctrl-C or, on Unix, SIGTERM
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!(error = %e, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
@@ -1,12 +1,14 @@
 //! WebRTC signaling over WebSocket for game client connections.
 
+use std::net::{IpAddr, SocketAddr};
 use std::sync::atomic::{AtomicU8, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use axum::extract::ws::{Message, WebSocket};
 use futures::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::MissedTickBehavior;
 use tracing::{debug, error, info, warn};
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::MediaEngine;
@@ -14,6 +16,7 @@ use webrtc::api::setting_engine::SettingEngine;
 use webrtc::api::APIBuilder;
 use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice::udp_network::UDPNetwork;
 use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
@@ -22,93 +25,254 @@ use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 
-use crate::bridge::Bridge;
-use crate::config::Config;
-
-/// Signal event type constants
-mod events {
-    pub const OFFER: &str = "offer";
-    pub const ANSWER: &str = "answer";
-    pub const CANDIDATE: &str = "candidate";
-}
-
-/// WebSocket signaling message
-#[derive(Debug, Serialize, Deserialize)]
-struct SignalMessage {
-    event: String,
-    data: serde_json::Value,
-}
+use crate::auth::AuthRequest;
+use crate::bridge::{Bridge, BridgeEvent};
+use crate::metrics::Metrics;
+use crate::protocol::{
+    ChannelMode, ClientHello, ClientMessage, ErrorCode, SdpType, ServerHello, ServerMessage,
+    SessionDescription, TransportKind, PROTOCOL_VERSION, SERVER_CAPABILITIES,
+};
+use crate::rate_limit::RateLimits;
+use crate::servers::ServerEntry;
+use crate::sessions::Session;
+use crate::shutdown::Shutdown;
+use crate::state::AppState;
+use crate::target_policy::{TargetPolicy, TargetRefused};
+use crate::transport::{ClientTransport, DataChannelTransport, WebSocketTransport};
+
+/// How long a client may take to send its `hello`
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Type alias for the WebSocket sender wrapped in `Arc<Mutex>`
-type WsSender = Arc<Mutex<futures::stream::SplitSink<WebSocket, Message>>>;
+pub type WsSender = Arc<Mutex<futures::stream::SplitSink<WebSocket, Message>>>;
+
+/// Type alias for the WebSocket receiver
+type WsReceiver = futures::stream::SplitStream<WebSocket>;
 
 /// Type alias for the bridge holder
 type BridgeHolder = Arc<Mutex<Option<Arc<Bridge>>>>;
 
+/// Per-session state needed to start the bridge once both channels are open
+#[derive(Clone)]
+struct BridgeContext {
+    server: Arc<ServerEntry>,
+    policy: Arc<TargetPolicy>,
+    client_id: String,
+    bridge: BridgeHolder,
+    ws_sender: WsSender,
+    metrics: Arc<Metrics>,
+    session: Arc<Session>,
+    /// Interval of `stats` events, zero if disabled
+    stats_interval: Duration,
+    shutdown: Arc<Shutdown>,
+    rate_limits: RateLimits,
+}
+
 /// Handle a new WebSocket connection for WebRTC signaling
-pub async fn handle_websocket(socket: WebSocket, config: Arc<Config>, client_id: String) {
-    info!(client_id = %client_id, "New WebSocket connection");
+pub async fn handle_websocket(
+    socket: WebSocket,
+    state: AppState,
+    server: ServerEntry,
+    client_id: String,
+    remote_addr: SocketAddr,
+    browser_ip: IpAddr,
+    auth: Option<String>,
+) {
+    info!(
+        client_id = %client_id,
+        server = %server.name,
+        remote = %remote_addr,
+        client_ip = %browser_ip,
+        "New WebSocket connection"
+    );
+    let metrics = state.metrics.clone();
+    metrics.ws_sessions.inc();
+    let sessions = state.sessions.clone();
+    let session = sessions.register(&client_id, &server.name, remote_addr, browser_ip);
 
     let (ws_sender, ws_receiver) = socket.split();
     let ws_sender: WsSender = Arc::new(Mutex::new(ws_sender));
 
+    run_session(
+        ws_sender.clone(),
+        ws_receiver,
+        state,
+        server,
+        client_id.clone(),
+        session,
+        auth,
+    )
+    .await;
+
+    // Close the socket properly so the client sees a clean close after errors
+    let _ = ws_sender.lock().await.close().await;
+
+    sessions.remove(&client_id);
+    metrics.ws_sessions.dec();
+    info!(client_id = %client_id, "WebSocket connection closed");
+}
+
+/// Send a single signaling message on a refused connection and close it
+pub async fn reject_websocket(mut socket: WebSocket, msg: &ServerMessage) {
+    if let Ok(json) = serde_json::to_string(msg) {
+        let _ = socket.send(Message::Text(json)).await;
+    }
+    let _ = socket.close().await;
+}
+
+/// Run the handshake, WebRTC negotiation and bridge for one session
+async fn run_session(
+    ws_sender: WsSender,
+    mut ws_receiver: WsReceiver,
+    state: AppState,
+    server: ServerEntry,
+    client_id: String,
+    session: Arc<Session>,
+    auth: Option<String>,
+) {
+    // Protocol handshake before any WebRTC resources are allocated
+    let Some(hello) = wait_for_hello(&mut ws_receiver, &ws_sender, &client_id).await else {
+        return;
+    };
+
+    info!(
+        client_id = %client_id,
+        version = hello.version,
+        capabilities = ?hello.capabilities,
+        "Client hello"
+    );
+
+    let credential = auth.as_deref().or(hello.auth.as_deref());
+    if !authenticate(
+        &state, &session, &server, credential, &ws_sender, &client_id,
+    )
+    .await
+    {
+        return;
+    }
+
+    // Browser and proxy share the same ICE servers and TURN credentials
+    let settings = state.settings();
+    let config = &settings.config;
+    let ice_servers = settings.ice_servers.for_session(&client_id);
+    let channel_mode = hello.channel_mode.unwrap_or(config.data_channel_mode);
+
+    let server_hello = ServerMessage::Hello(ServerHello {
+        version: PROTOCOL_VERSION,
+        capabilities: SERVER_CAPABILITIES
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        server: server.name.clone(),
+        ice_servers: ice_servers.clone(),
+        channel_mode,
+    });
+    if !send_signal(&ws_sender, &server_hello, &client_id).await {
+        return;
+    }
+
     // Create WebRTC peer connection
-    let peer = match create_peer_connection(config.public_ip.clone()).await {
+    let rtc_ice_servers = ice_servers.into_iter().map(Into::into).collect();
+    let peer = match create_peer_connection(
+        config.public_ip.clone(),
+        rtc_ice_servers,
+        state.udp_network.clone(),
+    )
+    .await
+    {
         Ok(p) => Arc::new(p),
         Err(e) => {
             error!(client_id = %client_id, error = %e, "Failed to create peer connection");
+            send_error(
+                &ws_sender,
+                ErrorCode::PeerConnectionFailed,
+                &e.to_string(),
+                &client_id,
+            )
+            .await;
             return;
         }
     };
 
     // Create data channels
-    let Some((write_channel, read_channel)) = create_data_channels(&peer, &client_id).await else {
-        return;
-    };
-
-    info!(client_id = %client_id, "Created write and read data channels");
+    let channel_init = data_channel_init(channel_mode, config.data_channel_lifetime);
+    let (write_channel, read_channel) =
+        match create_data_channels(&peer, channel_init, &client_id).await {
+            Ok(channels) => channels,
+            Err(e) => {
+                send_error(
+                    &ws_sender,
+                    ErrorCode::DataChannelFailed,
+                    &e.to_string(),
+                    &client_id,
+                )
+                .await;
+                let _ = peer.close().await;
+                return;
+            }
+        };
 
-    // Setup bridge management
-    let bridge: BridgeHolder = Arc::new(Mutex::new(None));
+    info!(client_id = %client_id, mode = ?channel_mode, "Created write and read data channels");
 
     // Setup callbacks
-    setup_bridge_callbacks(
-        &write_channel,
-        &read_channel,
-        config.clone(),
-        client_id.clone(),
-        bridge.clone(),
-    );
+    let bridge: BridgeHolder = Arc::new(Mutex::new(None));
+    let ctx = BridgeContext {
+        server: Arc::new(server),
+        policy: settings.target_policy.clone(),
+        client_id: client_id.clone(),
+        bridge: bridge.clone(),
+        ws_sender: ws_sender.clone(),
+        metrics: state.metrics.clone(),
+        session,
+        stats_interval: Duration::from_secs(config.stats_interval),
+        shutdown: state.shutdown.clone(),
+        rate_limits: RateLimits::from_config(config),
+    };
+    setup_bridge_callbacks(&write_channel, &read_channel, &ctx);
 
     setup_ice_handler(&peer, ws_sender.clone(), client_id.clone());
-    setup_connection_monitor(&peer, bridge.clone(), client_id.clone());
+    setup_connection_monitor(&peer, &ctx);
 
     // Send offer to client
     if !send_offer(&peer, &ws_sender, &client_id).await {
+        let _ = peer.close().await;
         return;
     }
 
     // Handle incoming WebSocket messages
-    handle_ws_messages(ws_receiver, peer, client_id.clone()).await;
+    handle_ws_messages(ws_receiver, peer.clone(), &ctx).await;
+    let _ = peer.close().await;
 
     // Cleanup
-    if let Some(b) = bridge.lock().await.take() {
+    let bridge = bridge.lock().await.take();
+    if let Some(b) = bridge {
         b.shutdown();
     }
+}
 
-    info!(client_id = %client_id, "WebSocket connection closed");
+/// Data channel options for a delivery mode
+fn data_channel_init(mode: ChannelMode, lifetime_ms: u16) -> RTCDataChannelInit {
+    let (ordered, max_retransmits, max_packet_life_time) = match mode {
+        ChannelMode::Reliable => (true, None, None),
+        ChannelMode::Unordered => (false, None, None),
+        ChannelMode::Unreliable => (false, Some(0), None),
+        ChannelMode::Lifetime => (false, None, Some(lifetime_ms)),
+    };
+
+    RTCDataChannelInit {
+        ordered: Some(ordered),
+        max_retransmits,
+        max_packet_life_time,
+        ..Default::default()
+    }
 }
 
 /// Create write and read data channels for game communication
 async fn create_data_channels(
     peer: &Arc<RTCPeerConnection>,
+    dc_options: RTCDataChannelInit,
     client_id: &str,
-) -> Option<(Arc<RTCDataChannel>, Arc<RTCDataChannel>)> {
-    let dc_options = RTCDataChannelInit {
-        ordered: Some(true),
-        ..Default::default()
-    };
-
+) -> Result<(Arc<RTCDataChannel>, Arc<RTCDataChannel>), webrtc::Error> {
     // Create "write" channel - for sending data TO the browser
     let write_channel = match peer
         .create_data_channel("write", Some(dc_options.clone()))
@@ -117,7 +281,7 @@ async fn create_data_channels(
         Ok(dc) => dc,
         Err(e) => {
             error!(client_id = %client_id, error = %e, "Failed to create write channel");
-            return None;
+            return Err(e);
         }
     };
 
@@ -126,20 +290,18 @@ async fn create_data_channels(
         Ok(dc) => dc,
         Err(e) => {
             error!(client_id = %client_id, error = %e, "Failed to create read channel");
-            return None;
+            return Err(e);
         }
     };
 
-    Some((write_channel, read_channel))
+    Ok((write_channel, read_channel))
 }
 
 /// Setup callbacks to start the bridge when both channels are open
 fn setup_bridge_callbacks(
     write_channel: &Arc<RTCDataChannel>,
     read_channel: &Arc<RTCDataChannel>,
-    config: Arc<Config>,
-    client_id: String,
-    bridge: BridgeHolder,
+    ctx: &BridgeContext,
 ) {
     let channels_open = Arc::new(AtomicU8::new(0));
 
@@ -147,9 +309,7 @@ fn setup_bridge_callbacks(
     setup_channel_on_open(
         write_channel,
         channels_open.clone(),
-        config.clone(),
-        client_id.clone(),
-        bridge.clone(),
+        ctx.clone(),
         write_channel.clone(),
         read_channel.clone(),
     );
@@ -158,9 +318,7 @@ fn setup_bridge_callbacks(
     setup_channel_on_open(
         read_channel,
         channels_open,
-        config,
-        client_id,
-        bridge,
+        ctx.clone(),
         write_channel.clone(),
         read_channel.clone(),
     );
@@ -170,60 +328,267 @@ fn setup_bridge_callbacks(
 fn setup_channel_on_open(
     channel: &Arc<RTCDataChannel>,
     channels_open: Arc<AtomicU8>,
-    config: Arc<Config>,
-    client_id: String,
-    bridge: BridgeHolder,
+    ctx: BridgeContext,
     write_channel: Arc<RTCDataChannel>,
     read_channel: Arc<RTCDataChannel>,
 ) {
     channel.on_open(Box::new(move || {
         let channels_open = channels_open.clone();
-        let config = config.clone();
-        let client_id = client_id.clone();
-        let bridge = bridge.clone();
+        let ctx = ctx.clone();
         let write_channel = write_channel.clone();
         let read_channel = read_channel.clone();
 
         Box::pin(async move {
             let count = channels_open.fetch_add(1, Ordering::SeqCst) + 1;
             if count == 2 {
-                start_bridge(config, client_id, bridge, write_channel, read_channel).await;
+                let transport = DataChannelTransport::new(write_channel, read_channel);
+                start_bridge(ctx, Arc::new(transport)).await;
             }
         })
     }));
 }
 
-/// Start the UDP bridge when both channels are ready
+/// Start the UDP bridge once a browser transport is ready
 async fn start_bridge(
-    config: Arc<Config>,
-    client_id: String,
-    bridge: BridgeHolder,
-    write_channel: Arc<RTCDataChannel>,
-    read_channel: Arc<RTCDataChannel>,
-) {
-    info!(client_id = %client_id, "Both channels open, starting bridge");
+    ctx: BridgeContext,
+    transport: Arc<dyn ClientTransport>,
+) -> Option<Arc<Bridge>> {
+    let client_id = ctx.client_id;
+    let kind = transport.kind();
+    info!(
+        client_id = %client_id,
+        server = %ctx.server.name,
+        transport = ?kind,
+        "Transport ready, starting bridge"
+    );
+
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
 
     match Bridge::new(
-        write_channel,
-        read_channel,
-        &config.server,
+        transport,
+        &ctx.server.address,
+        &ctx.policy,
         client_id.clone(),
+        events_tx,
+        ctx.metrics.clone(),
+        ctx.rate_limits,
     )
     .await
     {
         Ok(b) => {
             let b = Arc::new(b);
-            *bridge.lock().await = Some(b.clone());
-            tokio::spawn(async move {
-                b.start().await;
+            *ctx.bridge.lock().await = Some(b.clone());
+            ctx.session.set_bridge(&b);
+            tokio::spawn({
+                let b = b.clone();
+                async move {
+                    b.start().await;
+                }
             });
+
+            tokio::spawn(forward_bridge_events(
+                events_rx,
+                Arc::downgrade(&b),
+                ctx.ws_sender.clone(),
+                client_id.clone(),
+                ctx.stats_interval,
+            ));
+
+            let msg = ServerMessage::BridgeReady {
+                server: ctx.server.name.clone(),
+                transport: kind,
+            };
+            send_signal(&ctx.ws_sender, &msg, &client_id).await;
+            Some(b)
+        }
+        Err(e) => {
+            if let Some(refused) = e.downcast_ref::<TargetRefused>() {
+                warn!(client_id = %client_id, error = %refused, "Target refused by policy");
+                send_error(
+                    &ctx.ws_sender,
+                    ErrorCode::TargetRefused,
+                    &refused.to_string(),
+                    &client_id,
+                )
+                .await;
+            } else {
+                error!(client_id = %client_id, error = %e, "Failed to create bridge");
+                send_error(
+                    &ctx.ws_sender,
+                    ErrorCode::BridgeFailed,
+                    &e.to_string(),
+                    &client_id,
+                )
+                .await;
+            }
+            None
+        }
+    }
+}
+
+/// Relay bridge status events and periodic stats to the client until the
+/// bridge is dropped
+async fn forward_bridge_events(
+    mut events_rx: mpsc::UnboundedReceiver<BridgeEvent>,
+    bridge: Weak<Bridge>,
+    ws_sender: WsSender,
+    client_id: String,
+    stats_interval: Duration,
+) {
+    let mut stats_tick = (!stats_interval.is_zero()).then(|| {
+        let mut tick = tokio::time::interval(stats_interval);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        tick
+    });
+
+    loop {
+        let msg = tokio::select! {
+            event = events_rx.recv() => match event {
+                Some(BridgeEvent::ServerUnreachable(message)) => {
+                    ServerMessage::ServerUnreachable { message }
+                }
+                Some(BridgeEvent::RateLimited(message)) => {
+                    // The bridge is gone; closing the socket ends the session
+                    send_error(&ws_sender, ErrorCode::RateLimited, &message, &client_id).await;
+                    let _ = ws_sender.lock().await.close().await;
+                    break;
+                }
+                None => break,
+            },
+            _ = async {
+                match stats_tick.as_mut() {
+                    Some(tick) => tick.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let Some(bridge) = bridge.upgrade() else {
+                    break;
+                };
+                ServerMessage::Stats(bridge.stats().await)
+            }
+        };
+        if !send_signal(&ws_sender, &msg, &client_id).await {
+            break;
+        }
+    }
+}
+
+/// Send an `error` event with a stable code and a human readable message
+async fn send_error(ws_sender: &WsSender, code: ErrorCode, message: &str, client_id: &str) {
+    send_signal(ws_sender, &ServerMessage::error(code, message), client_id).await;
+}
+
+/// Check the session's credential and record its identity
+///
+/// A missing or rejected credential is answered with an `unauthorized` error.
+async fn authenticate(
+    state: &AppState,
+    session: &Session,
+    server: &ServerEntry,
+    credential: Option<&str>,
+    ws_sender: &WsSender,
+    client_id: &str,
+) -> bool {
+    let request = AuthRequest {
+        client_ip: session.client_ip(),
+        server: &server.name,
+    };
+    match state
+        .settings()
+        .auth
+        .authenticate(credential, request)
+        .await
+    {
+        Ok(Some(identity)) => {
+            info!(client_id = %client_id, identity = %identity, "Client authenticated");
+            session.set_identity(identity);
+            true
         }
+        Ok(None) => true,
         Err(e) => {
-            error!(client_id = %client_id, error = %e, "Failed to create bridge");
+            warn!(client_id = %client_id, error = %e, "Authentication failed");
+            send_error(
+                ws_sender,
+                ErrorCode::Unauthorized,
+                &e.to_string(),
+                client_id,
+            )
+            .await;
+            false
         }
     }
 }
 
+/// Wait for the client's `hello` and check its protocol version
+///
+/// Any other first message, a version mismatch or a timeout is answered with
+/// an `error` event and ends the session.
+async fn wait_for_hello(
+    receiver: &mut WsReceiver,
+    ws_sender: &WsSender,
+    client_id: &str,
+) -> Option<ClientHello> {
+    let text = loop {
+        let next = tokio::time::timeout(HELLO_TIMEOUT, receiver.next()).await;
+        match next {
+            Ok(Some(Ok(Message::Text(text)))) => break text,
+            Ok(Some(Ok(Message::Close(_)) | Err(_)) | None) => return None,
+            Ok(Some(Ok(_))) => {}
+            Err(_) => {
+                warn!(client_id = %client_id, "Client did not send hello in time");
+                send_error(
+                    ws_sender,
+                    ErrorCode::HandshakeRequired,
+                    "hello not received in time",
+                    client_id,
+                )
+                .await;
+                return None;
+            }
+        }
+    };
+
+    let (code, message) = match serde_json::from_str::<ClientMessage>(&text) {
+        Ok(ClientMessage::Hello(hello)) if hello.version == PROTOCOL_VERSION => {
+            return Some(hello);
+        }
+        Ok(ClientMessage::Hello(hello)) => (
+            ErrorCode::UnsupportedVersion,
+            format!(
+                "protocol version {} is not supported (expected {PROTOCOL_VERSION})",
+                hello.version
+            ),
+        ),
+        Ok(_) => (
+            ErrorCode::HandshakeRequired,
+            "first message must be hello".to_string(),
+        ),
+        Err(e) => (ErrorCode::InvalidMessage, e.to_string()),
+    };
+
+    warn!(client_id = %client_id, error = %message, "Handshake failed");
+    send_error(ws_sender, code, &message, client_id).await;
+    None
+}
+
+/// Serialize and send a signaling message to the client
+async fn send_signal(ws_sender: &WsSender, msg: &ServerMessage, client_id: &str) -> bool {
+    let json_str = match serde_json::to_string(msg) {
+        Ok(json) => json,
+        Err(e) => {
+            error!(client_id = %client_id, error = %e, "Failed to serialize signal");
+            return false;
+        }
+    };
+
+    let mut sender = ws_sender.lock().await;
+    if let Err(e) = sender.send(Message::Text(json_str)).await {
+        error!(client_id = %client_id, error = %e, "Failed to send signal");
+        return false;
+    }
+    true
+}
+
 /// Setup ICE candidate handler to send candidates to the client
 fn setup_ice_handler(peer: &Arc<RTCPeerConnection>, ws_sender: WsSender, client_id: String) {
     peer.on_ice_candidate(Box::new(move |candidate| {
@@ -237,18 +602,8 @@ fn setup_ice_handler(peer: &Arc<RTCPeerConnection>, ws_sender: WsSender, client_
 
             match c.to_json() {
                 Ok(json) => {
-                    let msg = SignalMessage {
-                        event: events::CANDIDATE.to_string(),
-                        data: serde_json::to_value(json).unwrap_or_default(),
-                    };
-
                     debug!(client_id = %client_id, "Sending ICE candidate");
-
-                    let json_str = serde_json::to_string(&msg).unwrap_or_default();
-                    let mut sender = ws_sender.lock().await;
-                    if let Err(e) = sender.send(Message::Text(json_str)).await {
-                        error!(client_id = %client_id, error = %e, "Failed to send ICE candidate");
-                    }
+                    send_signal(&ws_sender, &ServerMessage::Candidate(json), &client_id).await;
                 }
                 Err(e) => {
                     error!(client_id = %client_id, error = %e, "Failed to serialize ICE candidate");
@@ -259,24 +614,41 @@ fn setup_ice_handler(peer: &Arc<RTCPeerConnection>, ws_sender: WsSender, client_
 }
 
 /// Setup connection state change handler
-fn setup_connection_monitor(
-    peer: &Arc<RTCPeerConnection>,
-    bridge: BridgeHolder,
-    client_id: String,
-) {
+fn setup_connection_monitor(peer: &Arc<RTCPeerConnection>, ctx: &BridgeContext) {
+    let ctx = ctx.clone();
     peer.on_peer_connection_state_change(Box::new(move |state| {
-        let client_id = client_id.clone();
-        let bridge = bridge.clone();
+        let client_id = ctx.client_id.clone();
+        let bridge = ctx.bridge.clone();
+        let ws_sender = ctx.ws_sender.clone();
+        ctx.metrics.peer_state(state);
+        ctx.session.set_peer_state(state);
 
         Box::pin(async move {
             info!(client_id = %client_id, state = ?state, "Peer connection state changed");
 
+            if state == RTCPeerConnectionState::Failed {
+                send_error(
+                    &ws_sender,
+                    ErrorCode::IceFailed,
+                    "WebRTC connection failed",
+                    &client_id,
+                )
+                .await;
+            }
+
             match state {
                 RTCPeerConnectionState::Failed
                 | RTCPeerConnectionState::Disconnected
                 | RTCPeerConnectionState::Closed => {
-                    if let Some(b) = bridge.lock().await.take() {
-                        b.shutdown();
+                    // A WebSocket fallback bridge outlives the peer connection
+                    let mut bridge = bridge.lock().await;
+                    if bridge
+                        .as_ref()
+                        .is_some_and(|b| b.transport_kind() == TransportKind::Webrtc)
+                    {
+                        if let Some(b) = bridge.take() {
+                            b.shutdown();
+                        }
                     }
                 }
                 _ => {}
@@ -291,27 +663,23 @@ async fn send_offer(peer: &Arc<RTCPeerConnection>, ws_sender: &WsSender, client_
         Ok(o) => o,
         Err(e) => {
             error!(client_id = %client_id, error = %e, "Failed to create offer");
+            send_error(ws_sender, ErrorCode::OfferFailed, &e.to_string(), client_id).await;
             return false;
         }
     };
 
     if let Err(e) = peer.set_local_description(offer.clone()).await {
         error!(client_id = %client_id, error = %e, "Failed to set local description");
+        send_error(ws_sender, ErrorCode::OfferFailed, &e.to_string(), client_id).await;
         return false;
     }
 
-    let offer_msg = SignalMessage {
-        event: events::OFFER.to_string(),
-        data: serde_json::json!({
-            "type": events::OFFER,
-            "sdp": offer.sdp
-        }),
-    };
+    let offer_msg = ServerMessage::Offer(SessionDescription {
+        kind: SdpType::Offer,
+        sdp: offer.sdp,
+    });
 
-    let json_str = serde_json::to_string(&offer_msg).unwrap_or_default();
-    let mut sender = ws_sender.lock().await;
-    if let Err(e) = sender.send(Message::Text(json_str)).await {
-        error!(client_id = %client_id, error = %e, "Failed to send offer");
+    if !send_signal(ws_sender, &offer_msg, client_id).await {
         return false;
     }
 
@@ -319,33 +687,95 @@ async fn send_offer(peer: &Arc<RTCPeerConnection>, ws_sender: &WsSender, client_
     true
 }
 
-/// Handle incoming WebSocket messages (answer, candidates)
+/// Handle incoming WebSocket messages (signals and fallback game packets)
 async fn handle_ws_messages(
-    mut receiver: futures::stream::SplitStream<WebSocket>,
+    mut receiver: WsReceiver,
     peer: Arc<RTCPeerConnection>,
-    client_id: String,
+    ctx: &BridgeContext,
 ) {
-    while let Some(msg) = receiver.next().await {
+    let client_id = &ctx.client_id;
+    let ws_sender = &ctx.ws_sender;
+    // Bridge fed by binary frames once the client switched to the fallback
+    let mut ws_bridge: Option<Arc<Bridge>> = None;
+    let mut drain_announced = false;
+
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => msg,
+            () = ctx.shutdown.draining(), if !drain_announced => {
+                drain_announced = true;
+                let msg = ServerMessage::Draining {
+                    deadline_secs: ctx.shutdown.timeout().as_secs(),
+                };
+                send_signal(ws_sender, &msg, client_id).await;
+                continue;
+            }
+            () = ctx.session.disconnected() => {
+                info!(client_id = %client_id, "Session disconnected by admin");
+                send_error(
+                    ws_sender,
+                    ErrorCode::Disconnected,
+                    "disconnected by an administrator",
+                    client_id,
+                )
+                .await;
+                break;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+
         match msg {
             Ok(Message::Text(text)) => {
-                let signal: SignalMessage = match serde_json::from_str(&text) {
+                let signal: ClientMessage = match serde_json::from_str(&text) {
                     Ok(s) => s,
                     Err(e) => {
                         warn!(client_id = %client_id, error = %e, "Invalid signal message");
+                        send_error(
+                            ws_sender,
+                            ErrorCode::InvalidMessage,
+                            &e.to_string(),
+                            client_id,
+                        )
+                        .await;
                         continue;
                     }
                 };
 
-                match signal.event.as_str() {
-                    events::ANSWER => {
-                        handle_answer(&peer, &signal, &client_id).await;
+                let result = match signal {
+                    ClientMessage::Answer(answer) => handle_answer(&peer, answer, client_id)
+                        .await
+                        .map_err(|e| (ErrorCode::InvalidAnswer, e)),
+                    ClientMessage::Candidate(candidate) => {
+                        handle_candidate(&peer, candidate, client_id)
+                            .await
+                            .map_err(|e| (ErrorCode::InvalidCandidate, e))
                     }
-                    events::CANDIDATE => {
-                        handle_candidate(&peer, signal.data, &client_id).await;
+                    ClientMessage::UseWebsocket => {
+                        if ws_bridge.is_none() {
+                            ws_bridge = switch_to_websocket(&peer, ctx).await;
+                        }
+                        Ok(())
                     }
-                    _ => {
-                        warn!(client_id = %client_id, event = %signal.event, "Unknown signal event");
+                    ClientMessage::Hello(_) => {
+                        warn!(client_id = %client_id, "Duplicate hello");
+                        Err((
+                            ErrorCode::HandshakeRequired,
+                            "hello was already received".to_string(),
+                        ))
                     }
+                };
+
+                if let Err((code, message)) = result {
+                    send_error(ws_sender, code, &message, client_id).await;
+                }
+            }
+            Ok(Message::Binary(data)) => {
+                if let Some(bridge) = &ws_bridge {
+                    bridge.forward_to_server(&data).await;
+                } else {
+                    debug!(client_id = %client_id, "Binary frame without WebSocket transport");
                 }
             }
             Ok(Message::Close(_)) => {
@@ -363,51 +793,79 @@ async fn handle_ws_messages(
             }
         }
     }
+
+    if let Some(bridge) = ws_bridge {
+        bridge.shutdown();
+    }
+}
+
+/// Drop WebRTC and bridge game packets over the signaling WebSocket instead
+async fn switch_to_websocket(
+    peer: &Arc<RTCPeerConnection>,
+    ctx: &BridgeContext,
+) -> Option<Arc<Bridge>> {
+    info!(client_id = %ctx.client_id, "Switching to WebSocket transport");
+
+    let _ = peer.close().await;
+    let previous = ctx.bridge.lock().await.take();
+    if let Some(b) = previous {
+        b.shutdown();
+    }
+
+    let transport = WebSocketTransport::new(ctx.ws_sender.clone());
+    start_bridge(ctx.clone(), Arc::new(transport)).await
 }
 
 /// Handle SDP answer from client
-async fn handle_answer(peer: &Arc<RTCPeerConnection>, signal: &SignalMessage, client_id: &str) {
+async fn handle_answer(
+    peer: &Arc<RTCPeerConnection>,
+    answer: SessionDescription,
+    client_id: &str,
+) -> Result<(), String> {
     debug!(client_id = %client_id, "Received answer");
 
-    let sdp = signal
-        .data
-        .get("sdp")
-        .and_then(|s| s.as_str())
-        .unwrap_or("");
+    if answer.kind != SdpType::Answer {
+        warn!(client_id = %client_id, kind = ?answer.kind, "Unexpected SDP type");
+        return Err("session description type must be answer".to_string());
+    }
 
-    let answer = match RTCSessionDescription::answer(sdp.to_string()) {
+    let answer = match RTCSessionDescription::answer(answer.sdp) {
         Ok(a) => a,
         Err(e) => {
             error!(client_id = %client_id, error = %e, "Failed to parse SDP answer");
-            return;
+            return Err(e.to_string());
         }
     };
 
     if let Err(e) = peer.set_remote_description(answer).await {
         error!(client_id = %client_id, error = %e, "Failed to set remote description");
+        return Err(e.to_string());
     }
+
+    Ok(())
 }
 
 /// Handle ICE candidate from client
-async fn handle_candidate(peer: &Arc<RTCPeerConnection>, data: serde_json::Value, client_id: &str) {
+async fn handle_candidate(
+    peer: &Arc<RTCPeerConnection>,
+    candidate: RTCIceCandidateInit,
+    client_id: &str,
+) -> Result<(), String> {
     debug!(client_id = %client_id, "Received ICE candidate");
 
-    let candidate: RTCIceCandidateInit = match serde_json::from_value(data) {
-        Ok(c) => c,
-        Err(e) => {
-            warn!(client_id = %client_id, error = %e, "Invalid ICE candidate");
-            return;
-        }
-    };
-
     if let Err(e) = peer.add_ice_candidate(candidate).await {
         error!(client_id = %client_id, error = %e, "Failed to add ICE candidate");
+        return Err(e.to_string());
     }
+
+    Ok(())
 }
 
 /// Create a new WebRTC peer connection
 async fn create_peer_connection(
     public_ip: Option<String>,
+    ice_servers: Vec<RTCIceServer>,
+    udp_network: UDPNetwork,
 ) -> Result<RTCPeerConnection, Box<dyn std::error::Error + Send + Sync>> {
     let mut media_engine = MediaEngine::default();
     media_engine.register_default_codecs()?;
@@ -415,7 +873,10 @@ async fn create_peer_connection(
     let mut registry = Registry::new();
     registry = register_default_interceptors(registry, &mut media_engine)?;
 
+    // webrtc-ice only gathers UDP candidates; UDP-blocked clients fall back
+    // to TURN over TCP (see `--turn-listen-port`)
     let mut setting_engine = SettingEngine::default();
+    setting_engine.set_udp_network(udp_network);
 
     // Set public IP for NAT traversal if provided
     if let Some(ip) = public_ip {
@@ -432,10 +893,7 @@ async fn create_peer_connection(
         .build();
 
     let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_string()],
-            ..Default::default()
-        }],
+        ice_servers,
         ..Default::default()
     };
 
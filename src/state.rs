@@ -0,0 +1,178 @@
+//! Application state shared across HTTP handlers and signaling sessions.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+use webrtc::ice::udp_network::UDPNetwork;
+
+use crate::auth::Authenticator;
+use crate::config::Config;
+use crate::health::Probes;
+use crate::ice::{self, IceServers};
+use crate::limits::SessionLimits;
+use crate::metrics::Metrics;
+use crate::origin::OriginPolicy;
+use crate::server_browser::ServerBrowser;
+use crate::server_info::ServerInfoCache;
+use crate::servers::{ServerEntry, ServerRegistry};
+use crate::sessions::SessionRegistry;
+use crate::shutdown::Shutdown;
+use crate::target_policy::TargetPolicy;
+use crate::turn_server::TurnRelay;
+
+/// Configuration and everything built from it, replaced as a whole on reload
+///
+/// Sessions take what they need when they start, so a reload only affects
+/// sessions started afterwards.
+pub struct Settings {
+    /// Parsed configuration
+    pub config: Config,
+    /// Named game servers
+    pub servers: ServerRegistry,
+    /// Which upstream addresses bridges may connect to
+    pub target_policy: Arc<TargetPolicy>,
+    /// STUN/TURN servers for browser and proxy peer connections
+    pub ice_servers: IceServers,
+    /// Player authentication
+    pub auth: Authenticator,
+    /// Websites allowed to use the proxy
+    pub origins: OriginPolicy,
+}
+
+impl Settings {
+    /// Build the settings of a configuration, validating it along the way
+    pub fn new(
+        config: Config,
+        turn_relay: Option<Arc<TurnRelay>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let servers = ServerRegistry::from_config(&config)?;
+        let target_policy = TargetPolicy::from_config(&config, &servers)?;
+        let ice_servers = IceServers::from_config(&config, turn_relay)?;
+        let auth = Authenticator::from_config(&config)?;
+        let origins = OriginPolicy::from_config(&config)?;
+
+        Ok(Self {
+            config,
+            servers,
+            target_policy: Arc::new(target_policy),
+            ice_servers,
+            auth,
+            origins,
+        })
+    }
+}
+
+/// Application state shared across handlers
+#[derive(Clone)]
+pub struct AppState {
+    /// Current settings, see [`AppState::settings`]
+    settings: Arc<RwLock<Arc<Settings>>>,
+    /// Embedded TURN server, if running
+    pub turn_relay: Option<Arc<TurnRelay>>,
+    /// UDP sockets for the proxy's ICE agents (shared mux or port range)
+    pub udp_network: UDPNetwork,
+    /// Prometheus metrics
+    pub metrics: Arc<Metrics>,
+    /// Live browser sessions
+    pub sessions: Arc<SessionRegistry>,
+    /// Concurrent session limits
+    pub limits: Arc<SessionLimits>,
+    /// Draining state during shutdown
+    pub shutdown: Arc<Shutdown>,
+    /// Cached A2S answers of the configured servers
+    pub server_info: Arc<ServerInfoCache>,
+    /// Cached readiness probes of the configured servers
+    pub probes: Arc<Probes>,
+    /// Server list for `/api/servers`
+    pub server_browser: Arc<ServerBrowser>,
+}
+
+impl AppState {
+    /// Build the state from CLI configuration, validating it along the way
+    ///
+    /// Also starts the embedded TURN server when it is enabled.
+    pub async fn from_config(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let turn_relay = TurnRelay::start(&config).await?.map(Arc::new);
+        let udp_network = ice::udp_network(&config).await?;
+        let limits = SessionLimits::from_config(&config)?;
+
+        let shutdown = Shutdown::new(Duration::from_secs(config.shutdown_timeout));
+        let cache_ttl = Duration::from_secs(config.server_info_cache_secs);
+        let server_info = ServerInfoCache::new(cache_ttl);
+        let probes = Probes::new(cache_ttl);
+        let server_browser = ServerBrowser::from_config(&config);
+        let settings = Settings::new(config, turn_relay.clone())?;
+
+        Ok(Self {
+            settings: Arc::new(RwLock::new(Arc::new(settings))),
+            turn_relay,
+            udp_network,
+            metrics: Arc::new(Metrics::new()?),
+            sessions: Arc::new(SessionRegistry::default()),
+            limits: Arc::new(limits),
+            shutdown: Arc::new(shutdown),
+            server_info: Arc::new(server_info),
+            probes: Arc::new(probes),
+            server_browser: Arc::new(server_browser),
+        })
+    }
+
+    /// Current settings
+    ///
+    /// Hold on to the returned snapshot for the duration of a request or
+    /// session setup, so it sees one consistent configuration.
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings
+            .read()
+            .expect("settings lock poisoned")
+            .clone()
+    }
+
+    /// Replace the settings for everything that starts from now on
+    pub fn replace_settings(&self, settings: Settings) -> Arc<Settings> {
+        std::mem::replace(
+            &mut *self.settings.write().expect("settings lock poisoned"),
+            Arc::new(settings),
+        )
+    }
+
+    /// Pick the server a new session bridges to
+    ///
+    /// A custom target reuses the selected server's client settings; the
+    /// target policy decides whether the bridge may actually connect to it.
+    /// No new sessions are accepted while the proxy is draining.
+    pub fn session_server(
+        &self,
+        name: Option<&str>,
+        target: Option<String>,
+    ) -> Result<ServerEntry, (StatusCode, String)> {
+        if self.shutdown.is_draining() {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Proxy is shutting down".to_string(),
+            ));
+        }
+
+        let settings = self.settings();
+        let Some(mut server) = settings.servers.get(name).cloned() else {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Unknown server: {}", name.unwrap_or_default()),
+            ));
+        };
+
+        if let Some(target) = target {
+            if !settings.config.allow_custom_targets {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    "Custom targets are disabled".to_string(),
+                ));
+            }
+            server.address = target;
+        }
+
+        Ok(server)
+    }
+}
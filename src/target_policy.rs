@@ -0,0 +1,426 @@
+//! Policy deciding which upstream addresses a bridge may connect to.
+//!
+//! Every target is resolved once, checked against the policy, and the bridge
+//! then connects to the verified IP directly so that a second DNS lookup
+//! cannot swap in a different (e.g. internal) address.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use ipnet::IpNet;
+
+use crate::config::Config;
+use crate::servers::ServerRegistry;
+
+/// Reason a target was refused by the policy
+#[derive(Debug)]
+pub enum TargetRefused {
+    /// Target is not in `host:port` form
+    InvalidAddress(String),
+    /// DNS lookup failed or returned nothing
+    ResolveFailed(String),
+    /// Port is outside the allowed ranges
+    PortNotAllowed(u16),
+    /// No resolved address passed the CIDR and hostname rules
+    AddressNotAllowed(String),
+}
+
+impl fmt::Display for TargetRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress(target) => write!(f, "invalid target address: {target}"),
+            Self::ResolveFailed(host) => write!(f, "failed to resolve target host: {host}"),
+            Self::PortNotAllowed(port) => write!(f, "target port {port} is not allowed"),
+            Self::AddressNotAllowed(target) => write!(f, "target {target} is not allowed"),
+        }
+    }
+}
+
+impl std::error::Error for TargetRefused {}
+
+/// Loopback, unspecified and link-local networks, which only configured
+/// servers may resolve into unless `--allow-local-targets` is set
+const LOCAL_CIDRS: [&str; 6] = [
+    "0.0.0.0/8",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "::/128",
+    "::1/128",
+    "fe80::/10",
+];
+
+/// Most hosts kept in the DNS cache
+const MAX_DNS_CACHE: usize = 1024;
+
+/// Allowlist/denylist of upstream game server addresses
+#[derive(Debug)]
+pub struct TargetPolicy {
+    /// Networks a target may resolve into, in addition to `allow_hosts`
+    allow_cidrs: Vec<IpNet>,
+    /// Networks a custom target may never resolve into
+    deny_cidrs: Vec<IpNet>,
+    /// Networks only configured servers may resolve into
+    local_cidrs: Vec<IpNet>,
+    /// Allowed port ranges (empty = any)
+    allow_ports: Vec<RangeInclusive<u16>>,
+    /// Hostnames that are allowed regardless of `allow_cidrs` (lowercase)
+    allow_hosts: Vec<String>,
+    /// `host:port` of the configured servers (lowercase host)
+    allow_servers: Vec<(String, u16)>,
+    /// How long resolved addresses are reused before resolving again
+    dns_cache_ttl: Duration,
+    /// Resolved addresses by host
+    dns_cache: DashMap<String, (Instant, Vec<IpAddr>)>,
+}
+
+impl TargetPolicy {
+    /// Build the policy from CLI options
+    ///
+    /// The configured servers are always allowed on their own port, so the
+    /// policy only narrows what a browser can reach on its own.
+    pub fn from_config(
+        config: &Config,
+        servers: &ServerRegistry,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let allow_cidrs = config
+            .allow_target_cidr
+            .iter()
+            .map(|s| parse_cidr(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let deny_cidrs = config
+            .deny_target_cidr
+            .iter()
+            .map(|s| parse_cidr(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let allow_ports = config
+            .allow_target_ports
+            .iter()
+            .map(|s| parse_port_range(s))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let allow_hosts: Vec<String> = config
+            .allow_target_host
+            .iter()
+            .map(|h| h.to_ascii_lowercase())
+            .collect();
+
+        if config.allow_custom_targets && allow_cidrs.is_empty() && allow_hosts.is_empty() {
+            return Err("--allow-custom-targets requires --allow-target-cidr or \
+                        --allow-target-host, otherwise the proxy is an open UDP relay"
+                .into());
+        }
+
+        let allow_servers = servers
+            .iter()
+            .filter_map(|server| split_host_port(&server.address))
+            .map(|(host, port)| (host.to_ascii_lowercase(), port))
+            .collect();
+        let local_cidrs = if config.allow_local_targets {
+            Vec::new()
+        } else {
+            LOCAL_CIDRS
+                .iter()
+                .map(|s| parse_cidr(s))
+                .collect::<Result<_, _>>()?
+        };
+
+        Ok(Self {
+            allow_cidrs,
+            deny_cidrs,
+            local_cidrs,
+            allow_ports,
+            allow_hosts,
+            allow_servers,
+            dns_cache_ttl: Duration::from_secs(config.target_dns_cache_secs),
+            dns_cache: DashMap::new(),
+        })
+    }
+
+    /// Resolve a `host:port` target and return the first address the policy permits
+    pub async fn resolve(&self, target: &str) -> Result<SocketAddr, TargetRefused> {
+        let (host, port) = split_host_port(target)
+            .ok_or_else(|| TargetRefused::InvalidAddress(target.to_string()))?;
+
+        // Configured servers skip the port, deny and local rules
+        let configured = self
+            .allow_servers
+            .iter()
+            .any(|(h, p)| *p == port && h.eq_ignore_ascii_case(host));
+        if !configured
+            && !self.allow_ports.is_empty()
+            && !self.allow_ports.iter().any(|r| r.contains(&port))
+        {
+            return Err(TargetRefused::PortNotAllowed(port));
+        }
+        let host_allowed = self
+            .allow_hosts
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(host));
+
+        // IPv4-mapped IPv6 addresses are matched as the IPv4 address they reach
+        self.lookup(host)
+            .await?
+            .into_iter()
+            .map(|ip| ip.to_canonical())
+            .find(|ip| {
+                configured
+                    || (!self.deny_cidrs.iter().any(|net| net.contains(ip))
+                        && !self.local_cidrs.iter().any(|net| net.contains(ip))
+                        && (host_allowed || self.allow_cidrs.iter().any(|net| net.contains(ip))))
+            })
+            .map(|ip| SocketAddr::new(ip, port))
+            .ok_or_else(|| TargetRefused::AddressNotAllowed(target.to_string()))
+    }
+
+    /// Resolve a host, reusing cached results within the TTL
+    async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, TargetRefused> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+
+        let key = host.to_ascii_lowercase();
+        if let Some(entry) = self.dns_cache.get(&key) {
+            let (resolved_at, ref ips) = *entry;
+            if resolved_at.elapsed() < self.dns_cache_ttl {
+                return Ok(ips.clone());
+            }
+        }
+
+        let ips: Vec<IpAddr> = tokio::net::lookup_host((host, 0))
+            .await
+            .map_err(|_| TargetRefused::ResolveFailed(host.to_string()))?
+            .map(|addr| addr.ip())
+            .collect();
+
+        if ips.is_empty() {
+            return Err(TargetRefused::ResolveFailed(host.to_string()));
+        }
+
+        self.cache(key, ips.clone());
+        Ok(ips)
+    }
+
+    /// Remember resolved addresses, dropping expired entries once the cache
+    /// is full since browsers choose the hosts
+    fn cache(&self, host: String, ips: Vec<IpAddr>) {
+        if self.dns_cache_ttl.is_zero() {
+            return;
+        }
+        if self.dns_cache.len() >= MAX_DNS_CACHE {
+            self.dns_cache
+                .retain(|_, (resolved_at, _)| resolved_at.elapsed() < self.dns_cache_ttl);
+            if self.dns_cache.len() >= MAX_DNS_CACHE {
+                return;
+            }
+        }
+        self.dns_cache.insert(host, (Instant::now(), ips));
+    }
+}
+
+/// Split `host:port` (or `[v6]:port`) into its parts
+fn split_host_port(target: &str) -> Option<(&str, u16)> {
+    let (host, port) = target.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+/// Parse a CIDR, accepting bare IPs as single-address networks
+pub fn parse_cidr(s: &str) -> Result<IpNet, String> {
+    let s = s.trim();
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid CIDR: {s}"))
+}
+
+/// Parse a port or an inclusive `min-max` port range
+pub(crate) fn parse_port_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid port range: {s}");
+    let Some((min, max)) = s.split_once('-') else {
+        let port: u16 = s.parse().map_err(|_| invalid())?;
+        return Ok(port..=port);
+    };
+
+    let min: u16 = min.trim().parse().map_err(|_| invalid())?;
+    let max: u16 = max.trim().parse().map_err(|_| invalid())?;
+    if min > max {
+        return Err(invalid());
+    }
+    Ok(min..=max)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn policy(args: &[&str]) -> TargetPolicy {
+        let config = Config::parse_from(
+            ["webxash3d-proxy", "--server", "127.0.0.1:27015"]
+                .iter()
+                .chain(args),
+        );
+        let servers = ServerRegistry::from_config(&config).unwrap();
+        TargetPolicy::from_config(&config, &servers).unwrap()
+    }
+
+    #[tokio::test]
+    async fn configured_server_is_allowed_on_its_port_only() {
+        let policy = policy(&[]);
+        assert_eq!(
+            policy.resolve("127.0.0.1:27015").await.unwrap(),
+            "127.0.0.1:27015".parse().unwrap()
+        );
+        assert!(policy.resolve("127.0.0.1:27016").await.is_err());
+        assert!(policy.resolve("203.0.113.7:27015").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn configured_server_skips_port_and_deny_rules() {
+        let policy = policy(&[
+            "--allow-target-ports",
+            "27020-27030",
+            "--deny-target-cidr",
+            "127.0.0.0/8",
+        ]);
+        assert!(policy.resolve("127.0.0.1:27015").await.is_ok());
+        assert!(matches!(
+            policy.resolve("127.0.0.1:27016").await,
+            Err(TargetRefused::PortNotAllowed(27016))
+        ));
+    }
+
+    #[tokio::test]
+    async fn custom_targets_follow_cidr_and_port_rules() {
+        let policy = policy(&[
+            "--allow-custom-targets",
+            "--allow-target-cidr",
+            "10.0.0.0/8",
+            "--deny-target-cidr",
+            "10.0.0.5",
+            "--allow-target-ports",
+            "27015-27020",
+        ]);
+        assert!(policy.resolve("10.1.2.3:27020").await.is_ok());
+        assert!(matches!(
+            policy.resolve("10.1.2.3:27021").await,
+            Err(TargetRefused::PortNotAllowed(27021))
+        ));
+        assert!(matches!(
+            policy.resolve("10.0.0.5:27015").await,
+            Err(TargetRefused::AddressNotAllowed(_))
+        ));
+        assert!(policy.resolve("192.168.0.1:27015").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ipv4_mapped_targets_match_ipv4_rules() {
+        let policy = policy(&[
+            "--allow-custom-targets",
+            "--allow-target-cidr",
+            "0.0.0.0/0",
+            "--deny-target-cidr",
+            "10.0.0.0/8",
+        ]);
+        assert!(policy.resolve("[::ffff:10.0.0.5]:27015").await.is_err());
+        assert_eq!(
+            policy.resolve("[::ffff:203.0.113.7]:27015").await.unwrap(),
+            "203.0.113.7:27015".parse().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn local_targets_are_denied_by_default() {
+        let args = ["--allow-custom-targets", "--allow-target-cidr", "0.0.0.0/0"];
+        let policy = policy(&args);
+        for target in [
+            "127.0.0.2:27015",
+            "0.0.0.0:27015",
+            "169.254.169.254:80",
+            "[::ffff:127.0.0.1]:27016",
+        ] {
+            assert!(policy.resolve(target).await.is_err(), "{target}");
+        }
+
+        let policy = self::policy(&[&args[..], &["--allow-local-targets"]].concat());
+        assert!(policy.resolve("127.0.0.2:27015").await.is_ok());
+    }
+
+    #[test]
+    fn dns_cache_stays_bounded() {
+        let policy = policy(&[]);
+        let ips = vec![IpAddr::from([203, 0, 113, 7])];
+        let long_ago = Instant::now().checked_sub(policy.dns_cache_ttl).unwrap();
+        for i in 0..MAX_DNS_CACHE {
+            policy
+                .dns_cache
+                .insert(format!("old{i}.example.com"), (long_ago, ips.clone()));
+        }
+
+        // Expired entries make room once the cache is full
+        policy.cache("new.example.com".to_string(), ips.clone());
+        assert_eq!(policy.dns_cache.len(), 1);
+
+        for i in 0..MAX_DNS_CACHE {
+            policy.cache(format!("host{i}.example.com"), ips.clone());
+        }
+        assert_eq!(policy.dns_cache.len(), MAX_DNS_CACHE);
+        assert!(!policy.dns_cache.contains_key("host1023.example.com"));
+    }
+
+    #[test]
+    fn custom_targets_require_an_allow_rule() {
+        let config = Config::parse_from([
+            "webxash3d-proxy",
+            "--server",
+            "127.0.0.1:27015",
+            "--allow-custom-targets",
+        ]);
+        let servers = ServerRegistry::from_config(&config).unwrap();
+        assert!(TargetPolicy::from_config(&config, &servers).is_err());
+    }
+
+    #[test]
+    fn parses_host_port() {
+        assert_eq!(
+            split_host_port("example.com:27015"),
+            Some(("example.com", 27015))
+        );
+        assert_eq!(split_host_port("[::1]:27015"), Some(("::1", 27015)));
+        assert_eq!(split_host_port(":27015"), None);
+        assert_eq!(split_host_port("example.com"), None);
+        assert_eq!(split_host_port("example.com:port"), None);
+    }
+
+    #[test]
+    fn parses_cidrs() {
+        assert_eq!(
+            parse_cidr(" 10.0.0.0/8 ").unwrap(),
+            "10.0.0.0/8".parse().unwrap()
+        );
+        assert_eq!(
+            parse_cidr("10.0.0.5").unwrap(),
+            "10.0.0.5/32".parse().unwrap()
+        );
+        assert_eq!(parse_cidr("::1").unwrap(), "::1/128".parse().unwrap());
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn parses_port_ranges() {
+        assert_eq!(parse_port_range("27015").unwrap(), 27015..=27015);
+        assert_eq!(parse_port_range("27015 - 27020").unwrap(), 27015..=27020);
+        assert!(parse_port_range("27020-27015").is_err());
+        assert!(parse_port_range("70000").is_err());
+    }
+}
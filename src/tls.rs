@@ -0,0 +1,135 @@
+//! HTTPS/WSS termination for the web client and signaling.
+//!
+//! With `--tls-cert`/`--tls-key` the PEM files are checked every
+//! `--tls-reload-secs` and swapped in place when they change, so renewed
+//! certificates are picked up without dropping sessions. Without a domain,
+//! e.g. at a LAN party, `--tls-self-signed` generates a certificate at startup
+//! that browsers accept after a warning.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// Certificate chain and private key read from PEM files
+pub type CertifiedKey = (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>);
+
+/// TLS configuration of the HTTP listener, `None` to serve plain HTTP
+///
+/// Starts the background reload of certificate files.
+pub fn server_config(config: &Config) -> Result<Option<RustlsConfig>, Box<dyn std::error::Error>> {
+    if config.tls_self_signed {
+        return Ok(Some(RustlsConfig::from_config(self_signed(config)?)));
+    }
+    let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else {
+        return Ok(None);
+    };
+
+    let tls = RustlsConfig::from_config(rustls_config(read_pem(cert, key)?)?);
+    tokio::spawn(watch(
+        tls.clone(),
+        cert.clone(),
+        key.clone(),
+        Duration::from_secs(config.tls_reload_secs.max(1)),
+    ));
+    Ok(Some(tls))
+}
+
+/// Read a PEM certificate chain and private key
+pub fn read_pem(cert: &str, key: &str) -> Result<CertifiedKey, Box<dyn std::error::Error>> {
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(std::fs::File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate {cert}: {e}"))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {cert}").into());
+    }
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(std::fs::File::open(key)?))
+        .map_err(|e| format!("Invalid key {key}: {e}"))?
+        .ok_or_else(|| format!("No private key found in {key}"))?;
+    Ok((certs, key))
+}
+
+/// Server configuration for HTTP/1.1, which WebSocket upgrades need
+fn rustls_config(
+    (certs, key): CertifiedKey,
+) -> Result<Arc<rustls::ServerConfig>, Box<dyn std::error::Error>> {
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(tls))
+}
+
+/// Generate a certificate for localhost, the public IP, the bind address and
+/// `--tls-self-signed-name`
+fn self_signed(config: &Config) -> Result<Arc<rustls::ServerConfig>, Box<dyn std::error::Error>> {
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    names.extend(config.public_ip.iter().cloned());
+    if config
+        .host
+        .parse::<IpAddr>()
+        .is_ok_and(|ip| !ip.is_unspecified())
+    {
+        names.push(config.host.clone());
+    }
+    names.extend(
+        config
+            .tls_self_signed_name
+            .iter()
+            .map(|n| n.trim().to_string()),
+    );
+    names.retain(|n| !n.is_empty());
+    names.dedup();
+
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(names.clone())?;
+    let fingerprint = Sha256::digest(cert.der())
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    info!(?names, fingerprint, "Generated self-signed TLS certificate");
+
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+    rustls_config((vec![cert.der().clone()], key))
+}
+
+/// Reload the certificate whenever one of the files changes
+///
+/// Renewal tools may write the certificate and the key one after the other,
+/// so a pair that does not load yet is retried on the next check while the
+/// current certificate stays in use.
+async fn watch(tls: RustlsConfig, cert: String, key: String, interval: Duration) {
+    let mut loaded = modified(&cert, &key);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let current = modified(&cert, &key);
+        if current == loaded {
+            continue;
+        }
+        match read_pem(&cert, &key).and_then(rustls_config) {
+            Ok(config) => {
+                tls.reload_from_config(config);
+                loaded = current;
+                info!(cert, "TLS certificate reloaded");
+            }
+            Err(e) => warn!(cert, error = %e, "Failed to reload TLS certificate"),
+        }
+    }
+}
+
+/// Modification times of the certificate and key files
+fn modified(cert: &str, key: &str) -> Option<(SystemTime, SystemTime)> {
+    let mtime = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((mtime(cert)?, mtime(key)?))
+}
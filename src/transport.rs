@@ -0,0 +1,144 @@
+//! Browser-side transports a [`Bridge`] can forward game packets over.
+//!
+//! The UDP side of the bridge is the same for every transport; a transport
+//! only has to deliver server packets to the browser and feed browser packets
+//! back into [`Bridge::forward_to_server`].
+
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use axum::extract::ws::Message;
+use bytes::Bytes;
+use futures::SinkExt;
+use tracing::{error, info};
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::bridge::Bridge;
+use crate::protocol::TransportKind;
+use crate::signaling::WsSender;
+
+/// Error returned when a packet cannot be delivered to the browser
+pub type TransportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Packet transport between the proxy and the browser
+#[async_trait]
+pub trait ClientTransport: Send + Sync {
+    /// Which transport this is, as reported to the browser
+    fn kind(&self) -> TransportKind;
+
+    /// Deliver a game server packet to the browser
+    async fn send(&self, data: &Bytes) -> Result<(), TransportError>;
+
+    /// Start feeding browser packets into the bridge
+    ///
+    /// Transports whose packets arrive elsewhere (e.g. on the signaling
+    /// socket) leave this empty and call [`Bridge::forward_to_server`] directly.
+    fn attach(&self, _bridge: Weak<Bridge>) {}
+
+    /// Bytes queued for the browser but not yet sent, if the transport knows
+    async fn buffered_amount(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// WebRTC transport over the `write` and `read` data channels
+///
+/// - `write` channel: proxy sends TO browser (server → client)
+/// - `read` channel: proxy receives FROM browser (client → server)
+pub struct DataChannelTransport {
+    /// Channel for sending data TO the browser (server responses)
+    write_channel: Arc<RTCDataChannel>,
+    /// Channel for receiving data FROM the browser (client commands)
+    read_channel: Arc<RTCDataChannel>,
+}
+
+impl DataChannelTransport {
+    /// Wrap an open pair of game data channels
+    pub fn new(write_channel: Arc<RTCDataChannel>, read_channel: Arc<RTCDataChannel>) -> Self {
+        Self {
+            write_channel,
+            read_channel,
+        }
+    }
+}
+
+#[async_trait]
+impl ClientTransport for DataChannelTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Webrtc
+    }
+
+    async fn send(&self, data: &Bytes) -> Result<(), TransportError> {
+        self.write_channel.send(data).await?;
+        Ok(())
+    }
+
+    async fn buffered_amount(&self) -> Option<usize> {
+        Some(self.write_channel.buffered_amount().await)
+    }
+
+    fn attach(&self, bridge: Weak<Bridge>) {
+        // Handle incoming messages on the read channel
+        let on_message = bridge.clone();
+        self.read_channel
+            .on_message(Box::new(move |msg: DataChannelMessage| {
+                let bridge = on_message.clone();
+                Box::pin(async move {
+                    if let Some(bridge) = bridge.upgrade() {
+                        bridge.forward_to_server(&msg.data).await;
+                    }
+                })
+            }));
+
+        // Handle read channel close
+        let on_close = bridge.clone();
+        self.read_channel.on_close(Box::new(move || {
+            if let Some(bridge) = on_close.upgrade() {
+                info!(client_id = %bridge.client_id(), "Read channel closed");
+                bridge.shutdown();
+            }
+            Box::pin(async {})
+        }));
+
+        // Handle read channel errors
+        self.read_channel.on_error(Box::new(move |e| {
+            if let Some(bridge) = bridge.upgrade() {
+                error!(client_id = %bridge.client_id(), error = %e, "Read channel error");
+                bridge.shutdown();
+            }
+            Box::pin(async {})
+        }));
+    }
+}
+
+/// Fallback transport carrying packets as binary frames on the signaling socket
+///
+/// Packets travel over TCP, so loss turns into latency, but it works where
+/// WebRTC cannot connect at all.
+pub struct WebSocketTransport {
+    ws_sender: WsSender,
+}
+
+impl WebSocketTransport {
+    /// Send packets over the session's signaling WebSocket
+    pub fn new(ws_sender: WsSender) -> Self {
+        Self { ws_sender }
+    }
+}
+
+#[async_trait]
+impl ClientTransport for WebSocketTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Websocket
+    }
+
+    async fn send(&self, data: &Bytes) -> Result<(), TransportError> {
+        self.ws_sender
+            .lock()
+            .await
+            .send(Message::Binary(data.to_vec()))
+            .await?;
+        Ok(())
+    }
+}
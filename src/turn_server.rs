@@ -0,0 +1,814 @@
+//! Embedded TURN relay server.
+//!
+//! Listens on one UDP and one TCP port and accepts the same TURN REST API
+//! credentials that [`crate::ice`] hands out per signaling session, so no
+//! separate coturn deployment is needed.
+//!
+//! Relays only exchange packets with public addresses and the proxy's own
+//! addresses, so a browser cannot use them to reach the proxy's network.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use ipnet::IpNet;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tracing::{debug, info, warn};
+use webrtc::stun::attributes::{ATTR_MESSAGE_INTEGRITY, ATTR_REALM, ATTR_USERNAME};
+use webrtc::stun::integrity::MessageIntegrity;
+use webrtc::stun::message::{is_message, Message};
+use webrtc::stun::textattrs::TextAttribute;
+use webrtc::turn::auth::{generate_auth_key, AuthHandler};
+use webrtc::turn::relay::relay_range::RelayAddressGeneratorRanges;
+use webrtc::turn::relay::relay_static::RelayAddressGeneratorStatic;
+use webrtc::turn::relay::RelayAddressGenerator;
+use webrtc::turn::server::config::{ConnConfig, ServerConfig};
+use webrtc::turn::server::Server;
+use webrtc::util::vnet::net::Net;
+use webrtc::util::Conn;
+
+use crate::config::Config;
+use crate::ice::rest_api_password;
+use crate::target_policy::{parse_cidr, parse_port_range};
+
+/// Maximum size of a framed STUN/`ChannelData` message over TCP
+const MAX_TCP_MESSAGE: usize = 65536 + 4;
+
+/// Most TURN TCP streams open at once; further clients wait in the backlog
+const MAX_TCP_STREAMS: usize = 1024;
+
+/// First and longest pause after a failed TCP accept, e.g. out of descriptors
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Private, loopback, link-local and multicast networks relays may not reach
+/// unless `--turn-allow-private-peers` is set
+const PRIVATE_PEER_CIDRS: [&str; 14] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// How long a client that authenticated in time may keep refreshing its
+/// allocation after its credentials expired without sending a request
+#[allow(clippy::duration_suboptimal_units)] // `from_mins` needs a recent toolchain
+const ADMITTED_IDLE: Duration = Duration::from_secs(15 * 60);
+
+/// Most clients kept for the expired-credential grace at once
+const MAX_ADMITTED: usize = 65536;
+
+/// How often clients idle for [`ADMITTED_IDLE`] are forgotten
+const ADMITTED_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Running embedded TURN server
+pub struct TurnRelay {
+    /// TURN server handling both listeners, kept alive with the relay
+    _server: Server,
+    /// Shared secret used to derive and check session credentials
+    secret: String,
+    /// `turn:` URLs advertised to browsers
+    urls: Vec<String>,
+}
+
+impl TurnRelay {
+    /// Start the embedded TURN server if `--turn-listen-port` is set
+    pub async fn start(config: &Config) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Some(port) = config.turn_listen_port else {
+            return Ok(None);
+        };
+
+        let relay_ip: IpAddr = config
+            .turn_relay_ip
+            .as_deref()
+            .or(config.public_ip.as_deref())
+            .ok_or("--turn-listen-port requires --turn-relay-ip or --public-ip")?
+            .parse()
+            .map_err(|e| format!("Invalid TURN relay IP: {e}"))?;
+
+        // Reuse an external TURN secret if configured so one secret covers both
+        let secret = config
+            .turn_secret
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+
+        let listen_addr = format!("{}:{port}", config.host);
+        let udp = UdpSocket::bind(&listen_addr).await?;
+        let tcp = TcpTurnConn::bind(&listen_addr).await?;
+
+        let admissions = Arc::new(Admissions::new(secret.clone()));
+        tokio::spawn(sweep_admissions(Arc::downgrade(&admissions)));
+
+        let peers = Arc::new(PeerFilter::from_config(config, relay_ip)?);
+        let conn_configs = vec![
+            ConnConfig {
+                conn: Arc::new(AdmittingConn {
+                    inner: Arc::new(udp),
+                    admissions: admissions.clone(),
+                }),
+                relay_addr_generator: Box::new(PeerFilteredRelay {
+                    inner: relay_generator(config, relay_ip)?,
+                    peers: peers.clone(),
+                }),
+            },
+            ConnConfig {
+                conn: Arc::new(AdmittingConn {
+                    inner: Arc::new(tcp),
+                    admissions: admissions.clone(),
+                }),
+                relay_addr_generator: Box::new(PeerFilteredRelay {
+                    inner: relay_generator(config, relay_ip)?,
+                    peers,
+                }),
+            },
+        ];
+
+        let server = Server::new(ServerConfig {
+            conn_configs,
+            realm: config.turn_realm.clone(),
+            auth_handler: Arc::new(RestApiAuthHandler { admissions }),
+            channel_bind_timeout: Duration::from_secs(0),
+            alloc_close_notify: None,
+        })
+        .await?;
+
+        let host = format_host(relay_ip);
+        let urls = vec![
+            format!("turn:{host}:{port}?transport=udp"),
+            format!("turn:{host}:{port}?transport=tcp"),
+        ];
+
+        info!(
+            "Embedded TURN server listening on {} (udp/tcp), relaying via {}",
+            listen_addr, relay_ip
+        );
+
+        Ok(Some(Self {
+            _server: server,
+            secret,
+            urls,
+        }))
+    }
+
+    /// `turn:` URLs to advertise to browsers
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// Shared secret for TURN REST API credentials
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+}
+
+impl std::fmt::Debug for TurnRelay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TurnRelay")
+            .field("urls", &self.urls)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Relay sockets come from `--turn-relay-ports` or any ephemeral port
+fn relay_generator(
+    config: &Config,
+    relay_ip: IpAddr,
+) -> Result<Box<dyn RelayAddressGenerator + Send + Sync>, Box<dyn std::error::Error>> {
+    let net = Arc::new(Net::new(None));
+    let address = config.host.clone();
+
+    let Some(ref range) = config.turn_relay_ports else {
+        return Ok(Box::new(RelayAddressGeneratorStatic {
+            relay_address: relay_ip,
+            address,
+            net,
+        }));
+    };
+
+    let ports =
+        parse_port_range(range).map_err(|_| format!("Invalid TURN relay port range: {range}"))?;
+    let (min_port, max_port) = (*ports.start(), *ports.end());
+
+    Ok(Box::new(RelayAddressGeneratorRanges {
+        relay_address: relay_ip,
+        min_port,
+        max_port,
+        max_retries: 10,
+        address,
+        net,
+    }))
+}
+
+/// IPv6 literals need brackets inside URLs
+fn format_host(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("[{v6}]"),
+    }
+}
+
+/// Peers relays may exchange packets with
+#[derive(Debug)]
+struct PeerFilter {
+    /// Networks no relay may reach (`--deny-target-cidr`)
+    deny: Vec<IpNet>,
+    /// Networks only the proxy's own addresses are exempt from
+    private: Vec<IpNet>,
+    /// `--public-ip` and the relay address, where the proxy's candidates are
+    own: Vec<IpAddr>,
+}
+
+impl PeerFilter {
+    fn from_config(config: &Config, relay_ip: IpAddr) -> Result<Self, Box<dyn std::error::Error>> {
+        let deny = config
+            .deny_target_cidr
+            .iter()
+            .map(|s| parse_cidr(s))
+            .collect::<Result<_, _>>()?;
+        let private = if config.turn_allow_private_peers {
+            Vec::new()
+        } else {
+            PRIVATE_PEER_CIDRS
+                .iter()
+                .map(|s| parse_cidr(s))
+                .collect::<Result<_, _>>()?
+        };
+        let mut own = vec![relay_ip.to_canonical()];
+        if let Some(Ok(ip)) = config.public_ip.as_deref().map(str::parse::<IpAddr>) {
+            own.push(ip.to_canonical());
+        }
+
+        Ok(Self { deny, private, own })
+    }
+
+    fn allows(&self, peer: SocketAddr) -> bool {
+        let ip = peer.ip().to_canonical();
+        !self.deny.iter().any(|net| net.contains(&ip))
+            && (self.own.contains(&ip) || !self.private.iter().any(|net| net.contains(&ip)))
+    }
+}
+
+/// Relay address generator whose sockets only talk to peers the
+/// [`PeerFilter`] allows
+struct PeerFilteredRelay {
+    inner: Box<dyn RelayAddressGenerator + Send + Sync>,
+    peers: Arc<PeerFilter>,
+}
+
+#[async_trait]
+impl RelayAddressGenerator for PeerFilteredRelay {
+    fn validate(&self) -> Result<(), webrtc::turn::Error> {
+        self.inner.validate()
+    }
+
+    async fn allocate_conn(
+        &self,
+        use_ipv4: bool,
+        requested_port: u16,
+    ) -> Result<(Arc<dyn Conn + Send + Sync>, SocketAddr), webrtc::turn::Error> {
+        let (conn, relay_addr) = self.inner.allocate_conn(use_ipv4, requested_port).await?;
+        let conn = Arc::new(PeerFilteredConn {
+            inner: conn,
+            peers: self.peers.clone(),
+        });
+        Ok((conn, relay_addr))
+    }
+}
+
+/// Relay socket dropping packets to and from peers that are not allowed
+struct PeerFilteredConn {
+    inner: Arc<dyn Conn + Send + Sync>,
+    peers: Arc<PeerFilter>,
+}
+
+#[async_trait]
+impl Conn for PeerFilteredConn {
+    async fn connect(&self, addr: SocketAddr) -> webrtc::util::Result<()> {
+        if !self.peers.allows(addr) {
+            return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into());
+        }
+        self.inner.connect(addr).await
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc::util::Result<usize> {
+        let (n, _) = self.recv_from(buf).await?;
+        Ok(n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc::util::Result<(usize, SocketAddr)> {
+        loop {
+            let (n, peer) = self.inner.recv_from(buf).await?;
+            if self.peers.allows(peer) {
+                return Ok((n, peer));
+            }
+        }
+    }
+
+    async fn send(&self, _buf: &[u8]) -> webrtc::util::Result<usize> {
+        Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into())
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> webrtc::util::Result<usize> {
+        // Report the packet as sent so a client cannot flood the log
+        if !self.peers.allows(target) {
+            debug!(peer = %target, "TURN relay to denied peer dropped");
+            return Ok(buf.len());
+        }
+        self.inner.send_to(buf, target).await
+    }
+
+    fn local_addr(&self) -> webrtc::util::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.inner.remote_addr()
+    }
+
+    async fn close(&self) -> webrtc::util::Result<()> {
+        self.inner.close().await
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Send + Sync) {
+        self
+    }
+}
+
+/// Validates `<expiry>:<session id>` usernames issued by [`crate::ice`]
+///
+/// Credentials are short-lived, but clients keep refreshing allocations,
+/// permissions and channels for as long as the game runs. A client that
+/// authenticated before expiry may therefore keep using the same username
+/// from the same address, while new clients are refused.
+struct RestApiAuthHandler {
+    admissions: Arc<Admissions>,
+}
+
+impl AuthHandler for RestApiAuthHandler {
+    fn auth_handle(
+        &self,
+        username: &str,
+        realm: &str,
+        src_addr: SocketAddr,
+    ) -> Result<Vec<u8>, webrtc::turn::Error> {
+        let expiry = credential_expiry(username)
+            .ok_or_else(|| webrtc::turn::Error::Other(format!("Invalid username {username}")))?;
+        if expiry < unix_now() && !self.admissions.is_admitted(username, src_addr) {
+            debug!(username, src = %src_addr, "Expired TURN credentials");
+            return Err(webrtc::turn::Error::Other(format!(
+                "Expired username {username}"
+            )));
+        }
+
+        // A wrong password fails the message integrity check with this key
+        Ok(self.admissions.auth_key(username, realm))
+    }
+}
+
+/// Clients that passed message integrity, with the time of their last
+/// authenticated request
+///
+/// Entries are only added by [`AdmittingConn`] after checking a request's
+/// message integrity, so unauthenticated clients cannot grow the list.
+struct Admissions {
+    secret: String,
+    clients: DashMap<(String, SocketAddr), Instant>,
+}
+
+impl Admissions {
+    fn new(secret: String) -> Self {
+        Self {
+            secret,
+            clients: DashMap::new(),
+        }
+    }
+
+    /// Long-term credential key of a username
+    fn auth_key(&self, username: &str, realm: &str) -> Vec<u8> {
+        let password = rest_api_password(&self.secret, username);
+        generate_auth_key(username, realm, &password)
+    }
+
+    /// Whether the client authenticated recently enough to keep its username
+    fn is_admitted(&self, username: &str, src_addr: SocketAddr) -> bool {
+        self.clients
+            .get(&(username.to_string(), src_addr))
+            .is_some_and(|last_seen| last_seen.elapsed() < ADMITTED_IDLE)
+    }
+
+    /// Record the sender of an authenticated request
+    ///
+    /// Only requests whose credentials are unexpired or already admitted are
+    /// checked, and only those passing message integrity are recorded.
+    fn observe(&self, packet: &[u8], src_addr: SocketAddr) {
+        if !is_message(packet) {
+            return;
+        }
+        let mut message = Message::new();
+        if message.unmarshal_binary(packet).is_err() || !message.contains(ATTR_MESSAGE_INTEGRITY) {
+            return;
+        }
+        let (Ok(username), Ok(realm)) = (
+            TextAttribute::get_from_as(&message, ATTR_USERNAME),
+            TextAttribute::get_from_as(&message, ATTR_REALM),
+        ) else {
+            return;
+        };
+        let Some(expiry) = credential_expiry(&username.text) else {
+            return;
+        };
+
+        let admitted = self.is_admitted(&username.text, src_addr);
+        if expiry < unix_now() && !admitted {
+            return;
+        }
+        if !admitted && self.clients.len() >= MAX_ADMITTED {
+            debug!(src = %src_addr, "TURN admission list full");
+            return;
+        }
+
+        let integrity = MessageIntegrity(self.auth_key(&username.text, &realm.text));
+        if integrity.check(&mut message).is_ok() {
+            self.clients
+                .insert((username.text, src_addr), Instant::now());
+        }
+    }
+
+    /// Forget clients idle for longer than [`ADMITTED_IDLE`]
+    fn sweep(&self) {
+        self.clients
+            .retain(|_, last_seen| last_seen.elapsed() < ADMITTED_IDLE);
+    }
+}
+
+/// Sweep idle admissions until the TURN server is gone
+async fn sweep_admissions(admissions: Weak<Admissions>) {
+    let mut interval = tokio::time::interval(ADMITTED_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(admissions) = admissions.upgrade() else {
+            return;
+        };
+        admissions.sweep();
+    }
+}
+
+/// Expiry timestamp of a TURN REST API username
+fn credential_expiry(username: &str) -> Option<u64> {
+    username.split(':').next()?.parse().ok()
+}
+
+/// Current Unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Listener socket recording authenticated senders in [`Admissions`]
+struct AdmittingConn {
+    inner: Arc<dyn Conn + Send + Sync>,
+    admissions: Arc<Admissions>,
+}
+
+#[async_trait]
+impl Conn for AdmittingConn {
+    async fn connect(&self, addr: SocketAddr) -> webrtc::util::Result<()> {
+        self.inner.connect(addr).await
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc::util::Result<usize> {
+        self.inner.recv(buf).await
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc::util::Result<(usize, SocketAddr)> {
+        let (n, src_addr) = self.inner.recv_from(buf).await?;
+        self.admissions.observe(&buf[..n], src_addr);
+        Ok((n, src_addr))
+    }
+
+    async fn send(&self, buf: &[u8]) -> webrtc::util::Result<usize> {
+        self.inner.send(buf).await
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> webrtc::util::Result<usize> {
+        self.inner.send_to(buf, target).await
+    }
+
+    fn local_addr(&self) -> webrtc::util::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.inner.remote_addr()
+    }
+
+    async fn close(&self) -> webrtc::util::Result<()> {
+        self.inner.close().await
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Send + Sync) {
+        self
+    }
+}
+
+/// TURN over TCP presented as a packet connection
+///
+/// This is the fallback for UDP-blocked browsers in place of ICE-TCP, which
+/// webrtc-ice does not implement; traffic still goes through a relay.
+///
+/// Each accepted stream is split into STUN and `ChannelData` messages
+/// (RFC 5766 section 11.5) which are handed to the TURN server tagged with the
+/// stream's peer address; replies are written back to the matching stream.
+struct TcpTurnConn {
+    local_addr: SocketAddr,
+    incoming: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+    writers: Arc<DashMap<SocketAddr, Arc<Mutex<OwnedWriteHalf>>>>,
+}
+
+impl TcpTurnConn {
+    /// Bind the TCP listener and start accepting streams
+    async fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(256);
+        let writers = Arc::new(DashMap::new());
+
+        tokio::spawn(accept_loop(listener, tx, writers.clone()));
+
+        Ok(Self {
+            local_addr,
+            incoming: Mutex::new(rx),
+            writers,
+        })
+    }
+}
+
+/// Accept TCP streams and spawn a reader for each
+async fn accept_loop(
+    listener: TcpListener,
+    tx: mpsc::Sender<(Vec<u8>, SocketAddr)>,
+    writers: Arc<DashMap<SocketAddr, Arc<Mutex<OwnedWriteHalf>>>>,
+) {
+    let streams = Arc::new(Semaphore::new(MAX_TCP_STREAMS));
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+    loop {
+        let permit = streams
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("stream semaphore is never closed");
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(error = %e, retry_in = ?backoff, "TURN TCP accept failed");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+        backoff = ACCEPT_BACKOFF_MIN;
+
+        let tx = tx.clone();
+        let writers = writers.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let (reader, writer) = stream.into_split();
+            writers.insert(peer, Arc::new(Mutex::new(writer)));
+            if let Err(e) = read_framed(reader, peer, &tx).await {
+                debug!(peer = %peer, error = %e, "TURN TCP stream closed");
+            }
+            writers.remove(&peer);
+        });
+    }
+}
+
+/// Read STUN/`ChannelData` messages from a TCP stream
+async fn read_framed(
+    mut reader: tokio::net::tcp::OwnedReadHalf,
+    peer: SocketAddr,
+    tx: &mpsc::Sender<(Vec<u8>, SocketAddr)>,
+) -> std::io::Result<()> {
+    let mut header = [0u8; 4];
+    loop {
+        reader.read_exact(&mut header).await?;
+        let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+
+        // STUN messages start with 0b00, `ChannelData` with 0b01
+        let (body_len, padded_len) = if header[0] & 0xC0 == 0 {
+            (16 + length, 16 + length)
+        } else {
+            (length, length.next_multiple_of(4))
+        };
+        if 4 + padded_len > MAX_TCP_MESSAGE {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+
+        let mut message = vec![0u8; 4 + padded_len];
+        message[..4].copy_from_slice(&header);
+        reader.read_exact(&mut message[4..]).await?;
+        message.truncate(4 + body_len);
+
+        if tx.send((message, peer)).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait]
+impl Conn for TcpTurnConn {
+    async fn connect(&self, _addr: SocketAddr) -> webrtc::util::Result<()> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc::util::Result<usize> {
+        let (n, _) = self.recv_from(buf).await?;
+        Ok(n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc::util::Result<(usize, SocketAddr)> {
+        let Some((message, peer)) = self.incoming.lock().await.recv().await else {
+            return Err(webrtc::util::Error::ErrClosedListener);
+        };
+        let n = message.len().min(buf.len());
+        buf[..n].copy_from_slice(&message[..n]);
+        Ok((n, peer))
+    }
+
+    async fn send(&self, _buf: &[u8]) -> webrtc::util::Result<usize> {
+        Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into())
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> webrtc::util::Result<usize> {
+        let Some(writer) = self.writers.get(&target).map(|w| w.clone()) else {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into());
+        };
+        writer.lock().await.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    fn local_addr(&self) -> webrtc::util::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> webrtc::util::Result<()> {
+        self.incoming.lock().await.close();
+        Ok(())
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Send + Sync) {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use webrtc::stun::agent::TransactionId;
+    use webrtc::stun::message::BINDING_REQUEST;
+
+    use super::*;
+
+    fn peer_filter(args: &[&str]) -> PeerFilter {
+        let config = Config::parse_from(
+            ["webxash3d-proxy", "--server", "127.0.0.1:27015"]
+                .iter()
+                .chain(args),
+        );
+        PeerFilter::from_config(&config, "10.0.0.4".parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn relays_only_reach_public_and_own_addresses() {
+        let peers = peer_filter(&["--public-ip", "192.168.1.10"]);
+        for peer in ["203.0.113.7:27015", "10.0.0.4:50000", "192.168.1.10:50000"] {
+            assert!(peers.allows(peer.parse().unwrap()), "{peer}");
+        }
+        for peer in [
+            "127.0.0.1:27015",
+            "10.0.0.5:6379",
+            "169.254.169.254:80",
+            "[::ffff:127.0.0.1]:27015",
+            "[fe80::1]:27015",
+        ] {
+            assert!(!peers.allows(peer.parse().unwrap()), "{peer}");
+        }
+    }
+
+    #[test]
+    fn denied_targets_are_never_relayed_to() {
+        let peers = peer_filter(&[
+            "--turn-allow-private-peers",
+            "--deny-target-cidr",
+            "198.51.100.0/24,10.0.0.4",
+        ]);
+        assert!(peers.allows("10.0.0.5:6379".parse().unwrap()));
+        assert!(!peers.allows("198.51.100.1:27015".parse().unwrap()));
+        assert!(!peers.allows("10.0.0.4:50000".parse().unwrap()));
+    }
+
+    /// Request signed with the credentials of `username`
+    fn signed_request(username: &str, secret: &str) -> Vec<u8> {
+        let mut message = Message::new();
+        message
+            .build(&[
+                Box::new(TransactionId::new()),
+                Box::new(BINDING_REQUEST),
+                Box::new(TextAttribute::new(ATTR_USERNAME, username.to_string())),
+                Box::new(TextAttribute::new(ATTR_REALM, "realm".to_string())),
+                Box::new(MessageIntegrity::new_long_term_integrity(
+                    username.to_string(),
+                    "realm".to_string(),
+                    rest_api_password(secret, username),
+                )),
+            ])
+            .unwrap();
+        message.raw
+    }
+
+    #[test]
+    fn only_authenticated_requests_are_admitted() {
+        let admissions = Admissions::new("secret".to_string());
+        let client = "203.0.113.7:50000".parse().unwrap();
+        let (valid, expired) = (
+            format!("{}:a", unix_now() + 60),
+            format!("{}:b", unix_now() - 1),
+        );
+
+        admissions.observe(&signed_request(&valid, "wrong"), client);
+        admissions.observe(b"not stun", client);
+        assert!(!admissions.is_admitted(&valid, client));
+
+        admissions.observe(&signed_request(&expired, "secret"), client);
+        assert!(!admissions.is_admitted(&expired, client));
+
+        admissions.observe(&signed_request(&valid, "secret"), client);
+        assert!(admissions.is_admitted(&valid, client));
+    }
+
+    #[test]
+    fn expired_credentials_only_admit_known_clients() {
+        let admissions = Arc::new(Admissions::new("secret".to_string()));
+        let handler = RestApiAuthHandler {
+            admissions: admissions.clone(),
+        };
+        let (client, other) = (
+            "203.0.113.7:50000".parse().unwrap(),
+            "203.0.113.8:50000".parse().unwrap(),
+        );
+        let (valid, expired) = (
+            format!("{}:a", unix_now() + 60),
+            format!("{}:b", unix_now() - 1),
+        );
+
+        assert!(handler.auth_handle(&valid, "realm", client).is_ok());
+        assert!(handler.auth_handle(&expired, "realm", client).is_err());
+
+        admissions
+            .clients
+            .insert((expired.clone(), client), Instant::now());
+        assert!(handler.auth_handle(&expired, "realm", client).is_ok());
+        assert!(handler.auth_handle(&expired, "realm", other).is_err());
+        assert!(handler.auth_handle("garbage", "realm", client).is_err());
+    }
+
+    #[test]
+    fn sweep_forgets_idle_clients() {
+        let admissions = Admissions::new("secret".to_string());
+        let (idle, active) = (
+            "203.0.113.7:50000".parse().unwrap(),
+            "203.0.113.8:50000".parse().unwrap(),
+        );
+        let long_ago = Instant::now().checked_sub(ADMITTED_IDLE).unwrap();
+        admissions
+            .clients
+            .insert(("1:a".to_string(), idle), long_ago);
+        admissions
+            .clients
+            .insert(("1:a".to_string(), active), Instant::now());
+
+        admissions.sweep();
+        assert!(!admissions.clients.contains_key(&("1:a".to_string(), idle)));
+        assert!(admissions.is_admitted("1:a", active));
+    }
+}
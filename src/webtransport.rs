@@ -0,0 +1,453 @@
+//! WebTransport (HTTP/3) endpoint for the game bridge.
+//!
+//! Browsers open `https://<host>:<port>/webtransport?server=<name>` and
+//! exchange game packets as unreliable datagrams, one datagram per UDP
+//! packet. There is no SDP or ICE: the session is ready as soon as the
+//! extended CONNECT request is accepted.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::extract::Query;
+use axum::http::{Method, Response, StatusCode};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use h3::ext::Protocol;
+use quinn::crypto::rustls::QuicServerConfig;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::auth::AuthRequest;
+use crate::bridge::{Bridge, BridgeEvent};
+use crate::config::Config;
+use crate::limits::SessionPermit;
+use crate::protocol::TransportKind;
+use crate::rate_limit::RateLimits;
+use crate::servers::ServerEntry;
+use crate::state::AppState;
+use crate::transport::{ClientTransport, TransportError};
+
+/// HTTP/3 connection over quinn
+type H3Connection = h3::server::Connection<h3_quinn::Connection, Bytes>;
+
+/// Request stream of the extended CONNECT that opened the session
+type SessionStream = h3::server::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// Query parameters of the session request, same as for `/ws`
+#[derive(Deserialize)]
+struct SessionQuery {
+    server: Option<String>,
+    target: Option<String>,
+    /// Password or token when authentication is enabled
+    auth: Option<String>,
+}
+
+/// A CONNECT request that passed every check
+struct Admission {
+    server: ServerEntry,
+    /// Session slot, held for the lifetime of the session
+    permit: SessionPermit,
+    identity: Option<String>,
+}
+
+/// Bind the WebTransport endpoint if `--webtransport-port` is set
+pub fn bind(config: &Config) -> Result<Option<quinn::Endpoint>, Box<dyn std::error::Error>> {
+    let (Some(port), Some(cert), Some(key)) = (
+        config.webtransport_port,
+        &config.webtransport_cert,
+        &config.webtransport_key,
+    ) else {
+        return Ok(None);
+    };
+
+    let (certs, key) = crate::tls::read_pem(cert, key)?;
+
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(Duration::from_secs(5)));
+
+    let mut server_config =
+        quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+    server_config.transport_config(Arc::new(transport));
+
+    let addr: SocketAddr = format!("{}:{port}", config.host).parse()?;
+    let endpoint = quinn::Endpoint::server(server_config, addr)?;
+    info!(
+        "WebTransport listening on https://{}",
+        endpoint.local_addr()?
+    );
+
+    Ok(Some(endpoint))
+}
+
+/// Accept QUIC connections until the endpoint is closed
+pub async fn serve(endpoint: quinn::Endpoint, state: AppState) {
+    while let Some(incoming) = endpoint.accept().await {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let conn = match incoming.await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    debug!(error = %e, "WebTransport handshake failed");
+                    return;
+                }
+            };
+            handle_connection(conn, state).await;
+        });
+    }
+}
+
+/// Run one WebTransport session on a QUIC connection
+async fn handle_connection(conn: quinn::Connection, state: AppState) {
+    let remote = conn.remote_address();
+
+    let mut h3_conn = match h3::server::builder()
+        .enable_webtransport(true)
+        .enable_extended_connect(true)
+        .enable_datagram(true)
+        .max_webtransport_sessions(1)
+        .send_grease(true)
+        .build::<_, Bytes>(h3_quinn::Connection::new(conn.clone()))
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            debug!(remote = %remote, error = %e, "HTTP/3 setup failed");
+            return;
+        }
+    };
+
+    let Some((admission, mut stream)) = accept_session(&mut h3_conn, &state, remote).await else {
+        return;
+    };
+    let Admission {
+        server,
+        permit: _permit,
+        identity,
+    } = admission;
+
+    let client_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    info!(client_id = %client_id, remote = %remote, server = %server.name, "New WebTransport session");
+
+    let session =
+        state
+            .sessions
+            .register(&client_id, &server.name, remote, remote.ip().to_canonical());
+    if let Some(identity) = identity {
+        info!(client_id = %client_id, identity = %identity, "Client authenticated");
+        session.set_identity(identity);
+    }
+
+    // Datagrams are prefixed with the session's quarter stream ID (RFC 9297)
+    let session_id = stream.id().index();
+    let transport = Arc::new(DatagramTransport::new(conn.clone(), session_id));
+
+    // Without a signaling channel, bridge status events are only logged
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let settings = state.settings();
+    let bridge = match Bridge::new(
+        transport,
+        &server.address,
+        &settings.target_policy,
+        client_id.clone(),
+        events_tx,
+        state.metrics.clone(),
+        RateLimits::from_config(&settings.config),
+    )
+    .await
+    {
+        Ok(b) => Arc::new(b),
+        Err(e) => {
+            error!(client_id = %client_id, error = %e, "Failed to create bridge");
+            conn.close(0u32.into(), b"bridge failed");
+            state.sessions.remove(&client_id);
+            return;
+        }
+    };
+    session.set_bridge(&bridge);
+    tokio::spawn(bridge.clone().start());
+
+    loop {
+        tokio::select! {
+            datagram = conn.read_datagram() => {
+                let Ok(mut datagram) = datagram else {
+                    break;
+                };
+                if read_varint(&mut datagram) == Some(session_id) {
+                    bridge.forward_to_server(&datagram).await;
+                }
+            }
+            // Session ends when the browser closes the CONNECT stream
+            data = stream.recv_data() => {
+                if !matches!(data, Ok(Some(_))) {
+                    break;
+                }
+            }
+            // Keep the HTTP/3 control streams serviced; no further requests
+            next = h3_conn.accept() => {
+                if !matches!(next, Ok(Some(_))) {
+                    break;
+                }
+            }
+            Some(event) = events_rx.recv() => {
+                if let BridgeEvent::RateLimited(reason) = event {
+                    info!(client_id = %client_id, reason, "Session rate limited");
+                    break;
+                }
+            }
+            () = session.disconnected() => {
+                info!(client_id = %client_id, "Session disconnected by admin");
+                break;
+            }
+        }
+    }
+
+    bridge.shutdown();
+    conn.close(0u32.into(), b"");
+    state.sessions.remove(&client_id);
+    info!(client_id = %client_id, "WebTransport session closed");
+}
+
+/// Accept the first request of a connection as the WebTransport session
+///
+/// Anything other than a valid extended CONNECT is answered with an error.
+async fn accept_session(
+    h3_conn: &mut H3Connection,
+    state: &AppState,
+    remote: SocketAddr,
+) -> Option<(Admission, SessionStream)> {
+    let (request, mut stream) = match h3_conn.accept().await {
+        Ok(Some(resolver)) => match resolver.resolve_request().await {
+            Ok(req) => req,
+            Err(e) => {
+                debug!(remote = %remote, error = %e, "Invalid HTTP/3 request");
+                return None;
+            }
+        },
+        Ok(None) => return None,
+        Err(e) => {
+            debug!(remote = %remote, error = %e, "HTTP/3 connection failed");
+            return None;
+        }
+    };
+
+    let admission = match admit(state, remote, &request).await {
+        Ok(admission) => admission,
+        Err((status, message)) => {
+            warn!(remote = %remote, status = %status, message, "WebTransport session refused");
+            let response = Response::builder()
+                .status(status)
+                .body(())
+                .expect("building response should not fail");
+            let _ = stream.send_response(response).await;
+            let _ = stream.finish().await;
+            return None;
+        }
+    };
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header("sec-webtransport-http3-draft", "draft02")
+        .body(())
+        .expect("building response should not fail");
+    if let Err(e) = stream.send_response(response).await {
+        debug!(remote = %remote, error = %e, "Failed to accept WebTransport session");
+        return None;
+    }
+
+    Some((admission, stream))
+}
+
+/// Check a CONNECT request: server selection, session limits and authentication
+///
+/// Foreign origins get `403`, over-limit sessions `429`, missing or rejected
+/// credentials `401`.
+async fn admit(
+    state: &AppState,
+    remote: SocketAddr,
+    request: &http::Request<()>,
+) -> Result<Admission, (StatusCode, String)> {
+    let is_webtransport = request.method() == Method::CONNECT
+        && request.extensions().get::<Protocol>() == Some(&Protocol::WEB_TRANSPORT);
+    if !is_webtransport {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Expected a WebTransport CONNECT request".to_string(),
+        ));
+    }
+
+    let host = request.uri().authority().map(http::uri::Authority::as_str);
+    if let Err(origin) = state.settings().origins.check(request.headers(), host) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("Origin not allowed: {origin}"),
+        ));
+    }
+
+    let Query(query) = Query::<SessionQuery>::try_from_uri(request.uri())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let server = state.session_server(query.server.as_deref(), query.target)?;
+
+    let client_ip = remote.ip().to_canonical();
+    let permit = state.limits.acquire(client_ip).map_err(|limit| {
+        state.metrics.session_rejected(limit.as_str());
+        (StatusCode::TOO_MANY_REQUESTS, limit.to_string())
+    })?;
+
+    let request = AuthRequest {
+        client_ip,
+        server: &server.name,
+    };
+    let identity = state
+        .settings()
+        .auth
+        .authenticate(query.auth.as_deref(), request)
+        .await
+        .map_err(|e| (StatusCode::UNAUTHORIZED, e.to_string()))?;
+
+    Ok(Admission {
+        server,
+        permit,
+        identity,
+    })
+}
+
+/// Game packets as WebTransport datagrams on one session
+struct DatagramTransport {
+    conn: quinn::Connection,
+    /// Encoded quarter stream ID prepended to every datagram
+    prefix: Bytes,
+}
+
+impl DatagramTransport {
+    fn new(conn: quinn::Connection, session_id: u64) -> Self {
+        let mut prefix = BytesMut::new();
+        write_varint(&mut prefix, session_id);
+        Self {
+            conn,
+            prefix: prefix.freeze(),
+        }
+    }
+}
+
+#[async_trait]
+impl ClientTransport for DatagramTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Webtransport
+    }
+
+    async fn send(&self, data: &Bytes) -> Result<(), TransportError> {
+        let len = self.prefix.len() + data.len();
+        if self.conn.max_datagram_size().is_some_and(|max| len > max) {
+            // Datagrams cannot be fragmented; drop like an oversized UDP packet
+            debug!(
+                bytes = data.len(),
+                "Packet too large for a datagram, dropped"
+            );
+            return Ok(());
+        }
+
+        let mut datagram = BytesMut::with_capacity(len);
+        datagram.extend_from_slice(&self.prefix);
+        datagram.extend_from_slice(data);
+        self.conn.send_datagram(datagram.freeze())?;
+        Ok(())
+    }
+}
+
+/// Decode a QUIC variable-length integer
+fn read_varint(buf: &mut Bytes) -> Option<u64> {
+    let first = *buf.first()?;
+    let len = 1usize << (first >> 6);
+    if buf.len() < len {
+        return None;
+    }
+
+    let mut value = u64::from(first & 0x3f);
+    for byte in &buf[1..len] {
+        value = (value << 8) | u64::from(*byte);
+    }
+    buf.advance(len);
+    Some(value)
+}
+
+/// Encode a QUIC variable-length integer
+fn write_varint(buf: &mut BytesMut, value: u64) {
+    #[allow(clippy::cast_possible_truncation)]
+    match value {
+        0..=0x3f => buf.put_u8(value as u8),
+        0x40..=0x3fff => buf.put_u16(0x4000 | value as u16),
+        0x4000..=0x3fff_ffff => buf.put_u32(0x8000_0000 | value as u32),
+        _ => buf.put_u64(0xc000_0000_0000_0000 | value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: u64) -> Bytes {
+        let mut buf = BytesMut::new();
+        write_varint(&mut buf, value);
+        buf.freeze()
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for (value, len) in [
+            (0, 1),
+            (0x3f, 1),
+            (0x40, 2),
+            (0x3fff, 2),
+            (0x4000, 4),
+            (0x3fff_ffff, 4),
+            (0x4000_0000, 8),
+            ((1 << 62) - 1, 8),
+        ] {
+            let mut buf = encode(value);
+            assert_eq!(buf.len(), len, "{value:#x}");
+            assert_eq!(read_varint(&mut buf), Some(value));
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn decodes_rfc_9000_examples() {
+        for (bytes, value) in [
+            (
+                &[0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c][..],
+                151_288_809_941_952_652,
+            ),
+            (&[0x9d, 0x7f, 0x3e, 0x7d][..], 494_878_333),
+            (&[0x7b, 0xbd][..], 15_293),
+            (&[0x25][..], 37),
+            (&[0x40, 0x25][..], 37),
+        ] {
+            assert_eq!(read_varint(&mut Bytes::from_static(bytes)), Some(value));
+        }
+    }
+
+    #[test]
+    fn leaves_the_payload_after_the_varint() {
+        let mut buf = Bytes::from_static(&[0x40, 0x25, 0xff, 0xff]);
+        assert_eq!(read_varint(&mut buf), Some(37));
+        assert_eq!(&buf[..], &[0xff, 0xff]);
+    }
+
+    #[test]
+    fn rejects_truncated_varints() {
+        assert_eq!(read_varint(&mut Bytes::new()), None);
+        let mut buf = Bytes::from_static(&[0x9d, 0x7f]);
+        assert_eq!(read_varint(&mut buf), None);
+        assert_eq!(buf.len(), 2);
+    }
+}